@@ -0,0 +1,126 @@
+//! Benchmarks for the engine's hot paths, run with `cargo bench`.
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use test::{Bencher, black_box};
+
+use block::Block;
+use board::{GameBoard, Pixel};
+use game::Game;
+use input::Command;
+use mode::Mode;
+use rules::Ruleset;
+use tetriscolor::Color;
+use tetronimo::{Shape, Tetromino};
+use transform::Point;
+
+/// A standard board with a ragged stack on four rows that are full but
+/// for a well in column 4.
+fn messy_board() -> GameBoard {
+    let mut board = GameBoard::new(10, 22, 2, Pixel::new(0f64, 0f64));
+    let mut blocks = Vec::new();
+    for y in 0..4 {
+        for x in 0..10 {
+            if x != 4 {
+                blocks.push(Block::new(Color::black(), Point::new(x, y)));
+            }
+        }
+    }
+    for x in (0..10).filter(|&x| x != 4) {
+        for y in 4..(4 + x % 5) {
+            blocks.push(Block::new(Color::black(), Point::new(x, y)));
+        }
+    }
+    board.add_blocks(&blocks);
+    board
+}
+
+fn falling(shape: Shape) -> Tetromino {
+    let mut piece = Tetromino::new_shape(shape);
+    piece.put(Point::new(5, 20));
+    piece
+}
+
+/// Plays a game with random key presses, each held for a few frames.
+pub fn random_game(seed: u32, frames: usize) -> Game {
+    let mut rng = XorShiftRng::from_seed([seed, 1, 2, 3]);
+    let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), seed);
+    let mut held: Option<(Command, usize)> = None;
+    for _ in 0..frames {
+        held = match held {
+            Some((command, 0)) => {
+                game.on_release(command);
+                None
+            }
+            Some((command, n)) => Some((command, n - 1)),
+            None => {
+                let command = *rng.choose(&Command::all()).unwrap();
+                game.on_press(command);
+                Some((command, rng.gen_range(0, 6)))
+            }
+        };
+        game.on_update(1f64 / 60f64);
+        if game.is_over() {
+            break;
+        }
+    }
+    game
+}
+
+#[bench]
+fn check_piece(b: &mut Bencher) {
+    let board = messy_board();
+    let piece = falling(Shape::T);
+    b.iter(|| board.check_piece(black_box(&piece)));
+}
+
+#[bench]
+fn wipe_full_rows(b: &mut Bencher) {
+    let mut board = messy_board();
+    let mut piece = falling(Shape::I);
+    piece.rotate(&::transform::RotationDirection::Clockwise);
+    let column = piece.cells()[0].x;
+    piece.translate(&Point::new(4 - column, 0));
+    let piece = board.drop_piece(&piece);
+    board.add_blocks(&piece.blocks());
+    assert_eq!(4, board.clone().wipe_full_rows());
+    b.iter(|| {
+        let mut copy = board.clone();
+        copy.wipe_full_rows()
+    });
+}
+
+#[bench]
+fn ghost(b: &mut Bencher) {
+    let game = random_game(7, 600);
+    let piece = falling(Shape::J);
+    b.iter(|| game.ghost(black_box(&piece)));
+}
+
+#[bench]
+fn tetromino_blocks(b: &mut Bencher) {
+    let piece = falling(Shape::S);
+    b.iter(|| black_box(&piece).blocks());
+}
+
+#[bench]
+fn tetromino_cells(b: &mut Bencher) {
+    let piece = falling(Shape::S);
+    b.iter(|| black_box(&piece).cells());
+}
+
+/// Ten seconds of play at 60 frames per second.
+#[bench]
+fn simulated_game(b: &mut Bencher) {
+    let mut seed = 0;
+    b.iter(|| {
+        seed += 1;
+        random_game(seed, 600)
+    });
+}
+
+#[test]
+fn random_games_are_deterministic() {
+    let a = random_game(3, 300);
+    let b = random_game(3, 300);
+    assert_eq!(format!("{:?}", a.board()), format!("{:?}", b.board()));
+}
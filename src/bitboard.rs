@@ -0,0 +1,199 @@
+use super::transform::Point;
+
+/// Occupancy of a board with one bit per cell. Each row is a `u64`
+/// with bit `x` set when column `x` is filled, so boards can be up
+/// to 64 columns wide.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitBoard {
+    width: usize,
+    rows: Vec<u64>,
+}
+
+impl BitBoard {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= 64, "Bit boards are at most 64 wide, not {}", width);
+        BitBoard {
+            width: width,
+            rows: vec![0; height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Mask of a row with every column filled.
+    pub fn full_row(&self) -> u64 {
+        if self.width == 64 {
+            !0
+        } else {
+            (1 << self.width) - 1
+        }
+    }
+
+    pub fn row(&self, y: usize) -> u64 {
+        self.rows[y]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|r| *r == 0)
+    }
+
+    pub fn is_full(&self, y: usize) -> bool {
+        self.rows[y] == self.full_row()
+    }
+
+    /// Number of filled cells.
+    pub fn count(&self) -> usize {
+        self.rows.iter().map(|r| r.count_ones() as usize).sum()
+    }
+
+    /// True if the cell is outside the board or filled.
+    pub fn is_occupied(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.rows.len() {
+            return true;
+        }
+        self.rows[y as usize] & (1 << x) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize) {
+        self.rows[y] |= 1 << x;
+    }
+
+    /// True if none of the cells are occupied.
+    pub fn fits(&self, cells: &[Point]) -> bool {
+        cells.iter().all(|p| !self.is_occupied(p.x, p.y))
+    }
+
+    /// True if the cells fit after moving them down `dy` rows.
+    fn fits_below(&self, cells: &[Point], dy: i32) -> bool {
+        cells.iter().all(|p| !self.is_occupied(p.x, p.y - dy))
+    }
+
+    /// Fills the cells, which must be on the board.
+    pub fn place(&mut self, cells: &[Point]) {
+        for p in cells {
+            self.set(p.x as usize, p.y as usize);
+        }
+    }
+
+    /// Rows the cells can fall before landing, zero if they do not fit at all.
+    pub fn drop_distance(&self, cells: &[Point]) -> usize {
+        if !self.fits(cells) {
+            return 0;
+        }
+        let mut distance = 0;
+        while self.fits_below(cells, distance + 1) {
+            distance += 1;
+        }
+        distance as usize
+    }
+
+    /// Removes every full row, shifting the rows above down.
+    /// Returns the number of rows that were cleared.
+    pub fn clear_full_rows(&mut self) -> usize {
+        let full = self.full_row();
+        let height = self.rows.len();
+        self.rows.retain(|r| *r != full);
+        let cleared = height - self.rows.len();
+        self.rows.resize(height, 0);
+        cleared
+    }
+
+    /// Pushes the stack up by `lines` rows of garbage, each full except
+    /// for column `hole`. Returns false if filled cells were pushed off
+    /// the top.
+    pub fn add_garbage(&mut self, lines: usize, hole: usize) -> bool {
+        let height = self.rows.len();
+        let lines = lines.min(height);
+        let fits = self.rows[height - lines..].iter().all(|r| *r == 0);
+        let garbage = self.full_row() & !(1 << hole);
+        self.rows.truncate(height - lines);
+        for _ in 0..lines {
+            self.rows.insert(0, garbage);
+        }
+        fits
+    }
+
+    /// Height of the highest filled cell in a column, zero when empty.
+    pub fn column_height(&self, x: usize) -> usize {
+        self.rows
+            .iter()
+            .rposition(|r| r & (1 << x) != 0)
+            .map(|y| y + 1)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use transform::Point;
+
+    #[test]
+    fn out_of_bounds_is_occupied() {
+        let board = BitBoard::new(4, 3);
+        assert!(!board.is_occupied(0, 0));
+        assert!(board.is_occupied(-1, 0));
+        assert!(board.is_occupied(4, 0));
+        assert!(board.is_occupied(0, 3));
+        assert!(board.is_occupied(0, -1));
+    }
+
+    #[test]
+    fn clear_shifts_rows_down() {
+        let mut board = BitBoard::new(4, 4);
+        for x in 0..4 {
+            board.set(x, 0);
+            board.set(x, 2);
+        }
+        board.set(1, 1);
+        board.set(3, 3);
+        assert_eq!(2, board.clear_full_rows());
+        assert_eq!(0b0010, board.row(0));
+        assert_eq!(0b1000, board.row(1));
+        assert_eq!(0, board.row(2));
+        assert_eq!(2, board.count());
+        assert_eq!(2, board.column_height(3));
+    }
+
+    #[test]
+    fn drop_lands_on_the_stack() {
+        let mut board = BitBoard::new(4, 10);
+        board.set(1, 2);
+        let cells = [Point::new(1, 8), Point::new(2, 8)];
+        assert_eq!(5, board.drop_distance(&cells));
+        let floor = [Point::new(2, 8), Point::new(3, 8)];
+        assert_eq!(8, board.drop_distance(&floor));
+        let stuck = [Point::new(1, 2)];
+        assert_eq!(0, board.drop_distance(&stuck));
+    }
+
+    #[test]
+    fn garbage_pushes_up() {
+        let mut board = BitBoard::new(4, 4);
+        board.set(1, 0);
+        assert!(board.add_garbage(2, 3));
+        assert_eq!(0b0111, board.row(0));
+        assert_eq!(0b0111, board.row(1));
+        assert_eq!(0b0010, board.row(2));
+        assert!(!board.add_garbage(2, 0));
+        assert_eq!(0b1110, board.row(0));
+        assert_eq!(0b0111, board.row(3));
+    }
+
+    #[test]
+    fn widest_board() {
+        let mut board = BitBoard::new(64, 1);
+        for x in 0..64 {
+            board.set(x, 0);
+        }
+        assert!(board.is_full(0));
+        assert_eq!(1, board.clear_full_rows());
+        assert!(board.is_empty());
+    }
+}
@@ -1,11 +1,14 @@
 use super::tetriscolor::Color;
 use super::transform::Point;
+use super::bitboard::BitBoard;
 use super::block::Block;
-use super::tetronimo::Tetromino;
-use super::error;
+use super::tetronimo::{Shape, SlideDirection, Tetromino};
+use super::transform::RotationDirection;
+use super::error::{self, Error, ParseError};
 use super::Result;
 
 use na;
+use std::fmt;
 
 pub type Pixel = na::Point2<f64>;
 
@@ -16,12 +19,47 @@ enum RowPopulation {
     Mixed,
 }
 
-#[derive(Debug)]
+/// Size of a playing field. `height` counts the visible rows only,
+/// the `hidden` rows above them are where pieces spawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+    pub hidden: usize,
+}
+
+impl Dimensions {
+    pub fn standard() -> Self {
+        Dimensions {
+            width: 10,
+            height: 20,
+            hidden: 2,
+        }
+    }
+
+    /// Rejects fields that pieces cannot be played on.
+    pub fn check(self) -> Result<Self> {
+        if self.width < 4 || self.width > 40 || self.height < 4 || self.height > 100 ||
+           self.hidden < 2 || self.hidden > 20 {
+            return Err(error::Error::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+                hidden: self.hidden,
+            });
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct GameBoard {
     size_x: usize,
     size_y: usize,
     size_hidden: usize,
+    /// Colors of the filled cells, for drawing only.
     blocks: Vec<Option<Color>>,
+    /// Which cells are filled, for everything else.
+    bits: BitBoard,
     pub point: Pixel,
 }
 
@@ -31,15 +69,95 @@ impl GameBoard {
             size_x: x,
             size_y: y,
             blocks: vec![None; x * y],
+            bits: BitBoard::new(x, y),
             point: p,
             size_hidden: h,
         }
     }
 
+    /// Reads a board drawn top row first, `.` for an empty cell, a piece
+    /// letter for a block of that piece and `#` for garbage. Blank lines
+    /// and lines starting with `;` are skipped. The rows sit at the
+    /// bottom of a field `height` rows tall.
+    pub fn parse(text: &str, height: usize) -> Result<Self> {
+        // Rows keep their line number and indent for errors
+        let rows: Vec<(usize, usize, &str)> = text.lines()
+            .enumerate()
+            .map(|(number, line)| (number, line.len() - line.trim_left().len(), line.trim()))
+            .filter(|&(_, _, line)| !line.is_empty() && !line.starts_with(';'))
+            .collect();
+        let width = rows.first().map(|&(_, _, row)| row.chars().count()).unwrap_or(10);
+        let dimensions = try!(Dimensions {
+                width: width,
+                height: height,
+                hidden: 2,
+            }
+            .check());
+        if rows.len() > height {
+            return Err(Error::BadBoard(ParseError::new(format!("more than {} rows", height))));
+        }
+
+        let mut blocks = Vec::new();
+        for (i, &(number, indent, row)) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(Error::BadBoard(ParseError::at(number + 1,
+                                                          1,
+                                                          format!("expected {} cells", width))));
+            }
+            let y = (rows.len() - 1 - i) as i32;
+            for (x, cell) in row.chars().enumerate() {
+                let color = match cell {
+                    '.' => continue,
+                    '#' => Color::gray(),
+                    'I' | 'J' | 'L' | 'O' | 'S' | 'T' | 'Z' => {
+                        Shape::by_letter(cell).unwrap().color()
+                    }
+                    _ => {
+                        let message = format!("unknown cell {:?}", cell);
+                        return Err(Error::BadBoard(ParseError::at(number + 1,
+                                                                  indent + x + 1,
+                                                                  message)));
+                    }
+                };
+                blocks.push(Block::new(color, Point::new(x as i32, y)));
+            }
+        }
+        let mut board = GameBoard::try_new(dimensions, Pixel::new(0f64, 0f64)).unwrap();
+        board.add_blocks(&blocks);
+        Ok(board)
+    }
+
+    /// Like `new`, but rejects boards that pieces cannot be played on.
+    pub fn try_new(d: Dimensions, p: Pixel) -> Result<Self> {
+        let d = try!(d.check());
+        Ok(GameBoard::new(d.width, d.height + d.hidden, d.hidden, p))
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        Dimensions {
+            width: self.size_x,
+            height: self.height(),
+            hidden: self.size_hidden,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.size_x
+    }
+
     pub fn height(&self) -> usize {
         self.size_y - self.size_hidden
     }
 
+    /// True when no blocks are left anywhere on the board.
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    pub fn bits(&self) -> &BitBoard {
+        &self.bits
+    }
+
     fn copy_row(&mut self, lower: usize, upper: usize) {
         for i in 0..self.size_x {
             self.blocks[lower * self.size_x + i] = if upper < self.size_y {
@@ -51,61 +169,109 @@ impl GameBoard {
     }
 
     fn row_status(&self, row: usize) -> RowPopulation {
-        let mut row_pop: usize = 0;
-        for i in 0..self.size_x {
-            if self.blocks[row * self.size_x + i].is_some() {
-                row_pop += 1;
-            }
-        }
-
-        if row_pop == self.size_x {
+        if self.bits.is_full(row) {
             RowPopulation::Full
-        } else if row_pop == 0 {
+        } else if self.bits.row(row) == 0 {
             RowPopulation::Empty
         } else {
             RowPopulation::Mixed
         }
     }
 
-    pub fn wipe_full_rows(&mut self) {
-        let status: Vec<RowPopulation> = (0..self.size_y)
-            .map(|row| self.row_status(row))
+    /// Removes every full row, shifting the rows above down.
+    /// Returns the number of rows that were cleared.
+    pub fn wipe_full_rows(&mut self) -> usize {
+        // The colors drop the same rows as the bits, full rows can sit
+        // above empty ones on boards that were set up rather than played
+        let full: Vec<bool> = (0..self.size_y)
+            .map(|row| self.row_status(row) == RowPopulation::Full)
             .collect();
+        let cleared = self.bits.clear_full_rows();
 
-        let mut lower = 0;
         let mut upper = 0;
-
-
-        while lower < self.size_y {
-            if status[lower] == RowPopulation::Empty {
-                return;
-            }
-            while status[upper] == RowPopulation::Full {
+        for lower in 0..self.size_y {
+            while upper < self.size_y && full[upper] {
                 upper += 1;
             }
             self.copy_row(lower, upper);
-            lower += 1;
             upper += 1;
         }
+        cleared
+    }
+
+    /// Pushes the stack up by `lines` gray rows with a hole in column
+    /// `hole`. Returns false if blocks were pushed off the top.
+    pub fn add_garbage(&mut self, lines: usize, hole: usize) -> bool {
+        let lines = lines.min(self.size_y);
+        let fits = self.bits.add_garbage(lines, hole);
+        let width = self.size_x;
+        self.blocks.truncate((self.size_y - lines) * width);
+        let gray = Color::gray();
+        let mut garbage = Vec::with_capacity(lines * width);
+        for _ in 0..lines {
+            garbage.extend((0..width).map(|x| if x == hole { None } else { Some(gray) }));
+        }
+        garbage.extend(self.blocks.drain(..));
+        self.blocks = garbage;
+        fits
     }
 
     pub fn check_piece(&self, piece: &Tetromino) -> bool {
-        piece.blocks()
-            .iter()
-            .map(|b| {
-                if self.index(&b.point).is_ok() {
-                    self.is_empty(&b.point)
-                } else {
-                    false
-                }
-            })
-            .fold(true, |a, b| a && b)
-    }
-
-    fn is_empty(&self, p: &Point) -> bool {
-        match self.blocks[self.index(p).unwrap()] {
-            Some(_) => false,
-            None => true,
+        self.bits.fits(&piece.cells())
+    }
+
+    /// Slides a piece one column, if there is room for it.
+    pub fn slide_piece(&self, piece: &Tetromino, direction: SlideDirection) -> Option<Tetromino> {
+        let mut test_piece = piece.clone();
+        test_piece.slide(direction);
+        if self.check_piece(&test_piece) {
+            Some(test_piece)
+        } else {
+            None
+        }
+    }
+
+    /// Rotates a piece, trying each wall kick in turn. On success
+    /// returns the moved piece and the index of the kick that was used.
+    pub fn rotate_piece(&self,
+                        piece: &Tetromino,
+                        direction: RotationDirection)
+                        -> Option<(Tetromino, usize)> {
+        let mut rotated = piece.clone();
+        rotated.rotate(&direction);
+        let translations = rotated.wall_kick_options(&direction);
+        for (kick, test_translate) in translations.iter().enumerate() {
+            let mut test_piece = rotated.clone();
+            test_piece.translate(test_translate);
+            if self.check_piece(&test_piece) {
+                return Some((test_piece, kick));
+            }
+        }
+        None
+    }
+
+    /// Where a piece would land if dropped straight down.
+    pub fn drop_piece(&self, piece: &Tetromino) -> Tetromino {
+        let mut landed = piece.clone();
+        landed.translate(&Point::new(0, -(self.bits.drop_distance(&piece.cells()) as i32)));
+        landed
+    }
+
+    /// An empty board with the same dimensions as this one.
+    pub fn empty_copy(&self) -> GameBoard {
+        GameBoard::new(self.size_x, self.size_y, self.size_hidden, self.point)
+    }
+
+    /// True if the point is outside the board or already holds a block.
+    pub fn is_occupied(&self, p: &Point) -> bool {
+        self.bits.is_occupied(p.x, p.y)
+    }
+
+    /// Color of the block at the point, `None` if there is none.
+    pub fn cell(&self, p: &Point) -> Option<Color> {
+        match self.index(p) {
+            Ok(index) => self.blocks[index],
+            Err(_) => None,
         }
     }
 
@@ -139,6 +305,18 @@ impl GameBoard {
         }
     }
 
+    /// Locks a piece into the stack, if it fits there.
+    pub fn place(&mut self, piece: &Tetromino) -> Result<()> {
+        let blocks = piece.blocks();
+        for b in &blocks {
+            if self.is_occupied(&b.point) {
+                return Err(error::Error::IllegalPlacement(b.point));
+            }
+        }
+        self.add_blocks(&blocks);
+        Ok(())
+    }
+
     pub fn add_blocks(&mut self, blocks: &Vec<Block>) {
         for &Block { point: p, color: mut c } in blocks {
             let index = self.index(&p).unwrap();
@@ -149,6 +327,7 @@ impl GameBoard {
                 c = Color::black();
 
             }
+            self.bits.set(p.x as usize, p.y as usize);
             c.alpha = 0.8f32;
             self.blocks[index] = Some(c);
         }
@@ -174,6 +353,23 @@ impl GameBoard {
     }
 }
 
+/// The board the way `parse` reads it, from the highest block down.
+impl fmt::Display for GameBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let top = (0..self.size_y).rev().find(|&y| self.bits.row(y) != 0).unwrap_or(0);
+        for y in (0..top + 1).rev() {
+            let row: String = self.blocks[y * self.size_x..(y + 1) * self.size_x]
+                .iter()
+                .map(|cell| match *cell {
+                    Some(color) => Shape::by_color(&color).map_or('#', |s| s.letter()),
+                    None => '.',
+                })
+                .collect();
+            try!(writeln!(f, "{}", row));
+        }
+        Ok(())
+    }
+}
 
 #[test]
 fn new_board() {
@@ -187,20 +383,101 @@ fn new_board() {
 
 #[test]
 fn copy_rows() {
-    let mut board = GameBoard::new(2, 2, 0, Pixel::new(0f64, 0f64));
-    let c1 = Color::black();
-    let c2 = Color::white();
-    board.blocks = vec![None, Some(c1), Some(c2), None];
+    let mut board = GameBoard::parse("#...\n.#..\n", 4).unwrap();
     board.copy_row(0, 1);
-    assert_eq!(vec![Some(c2), None, Some(c2), None], board.blocks);
+    assert_eq!("#...\n#...\n", board.to_string());
 }
 
 #[test]
 fn row_status_check() {
-    let mut board = GameBoard::new(2, 3, 0, Pixel::new(0f64, 0f64));
-    let c1 = Color::black();
-    board.blocks = vec![None, None, Some(c1), None, Some(c1), Some(c1)];
-    assert_eq!(RowPopulation::Empty, board.row_status(0));
-    assert_eq!(RowPopulation::Mixed, board.row_status(1));
-    assert_eq!(RowPopulation::Full, board.row_status(2));
+    let board = GameBoard::parse("####\n#...\n", 4).unwrap();
+    assert_eq!(RowPopulation::Mixed, board.row_status(0));
+    assert_eq!(RowPopulation::Full, board.row_status(1));
+    assert_eq!(RowPopulation::Empty, board.row_status(2));
+}
+
+#[test]
+fn empty_after_clearing_everything() {
+    assert!(GameBoard::parse("", 4).unwrap().is_empty());
+    let mut board = GameBoard::parse("#...\n####\n", 4).unwrap();
+    assert_eq!(1, board.wipe_full_rows());
+    assert!(!board.is_empty());
+    let rest: Vec<Block> = (1..4).map(|x| Block::new(Color::gray(), Point::new(x, 0))).collect();
+    board.add_blocks(&rest);
+    assert_eq!(1, board.wipe_full_rows());
+    assert!(board.is_empty());
+}
+
+#[test]
+fn clearing_a_floating_row() {
+    let mut board = GameBoard::parse("#...\n####\n....\n.#..\n", 4).unwrap();
+    assert_eq!(1, board.wipe_full_rows());
+    assert_eq!("#...\n....\n.#..\n", board.to_string());
+    assert!(board.cell(&Point::new(1, 2)).is_none());
+    assert!(board.cell(&Point::new(0, 2)).is_some());
+}
+
+#[test]
+fn invalid_dimensions() {
+    let origin = Pixel::new(0f64, 0f64);
+    let board = GameBoard::try_new(Dimensions::standard(), origin).unwrap();
+    assert_eq!(Dimensions::standard(), board.dimensions());
+    for &(width, height, hidden) in &[(3, 20, 2), (41, 20, 2), (10, 3, 2), (10, 20, 0)] {
+        let d = Dimensions {
+            width: width,
+            height: height,
+            hidden: hidden,
+        };
+        assert!(GameBoard::try_new(d, origin).is_err());
+    }
+    assert!(Dimensions { width: 4, height: 4, hidden: 2 }.check().is_ok());
+    assert!(Dimensions { width: 40, height: 60, hidden: 4 }.check().is_ok());
+}
+
+#[test]
+fn illegal_placement() {
+    use super::tetronimo::Shape;
+    let mut board = GameBoard::new(10, 22, 2, Pixel::new(0f64, 0f64));
+    let mut piece = Tetromino::new_shape(Shape::O);
+    piece.put(Point::new(4, 1));
+    assert!(board.place(&piece).is_ok());
+    match board.place(&piece) {
+        Err(error::Error::IllegalPlacement(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    piece.put(Point::new(-3, 1));
+    assert!(board.place(&piece).is_err());
+}
+
+#[test]
+fn parse_a_board() {
+    let board = GameBoard::parse("; a hole to fill\n\n....######\n....######\n", 20).unwrap();
+    assert_eq!(10, board.width());
+    assert_eq!(12, board.bits().count());
+    assert!(board.is_occupied(&Point::new(4, 1)));
+    assert!(!board.is_occupied(&Point::new(3, 0)));
+    match GameBoard::parse("....\n...\n", 20) {
+        Err(Error::BadBoard(ParseError { position: Some((2, 1)), .. })) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    // Typos and lowercase letters are not taken for blocks
+    for &(text, column) in &[("....\n.x..\n", 2), ("....\n  ..t.\n", 5), ("0...\n", 1)] {
+        match GameBoard::parse(text, 20) {
+            Err(Error::BadBoard(ParseError { position: Some((_, c)), .. })) if c == column => {}
+            other => panic!("Unexpected {:?} for {:?}", other, text),
+        }
+    }
+}
+
+#[test]
+fn text_round_trip() {
+    let text = "....T.....\n...TTT..OO\n#IIII#..OO\n";
+    let board = GameBoard::parse(text, 20).unwrap();
+    let shape = |x, y| board.cell(&Point::new(x, y)).and_then(|c| Shape::by_color(&c));
+    assert_eq!(Some(Shape::T), shape(4, 2));
+    assert_eq!(Some(Shape::O), shape(9, 0));
+    assert_eq!(None, shape(0, 0));
+    assert!(board.is_occupied(&Point::new(0, 0)));
+    assert_eq!(text, board.to_string());
+    assert_eq!("..........\n", GameBoard::parse("", 20).unwrap().to_string());
 }
@@ -0,0 +1,406 @@
+use toml;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::bitboard::BitBoard;
+use super::board::GameBoard;
+use super::error::{Error, ParseError};
+use super::game::Game;
+use super::input::Command;
+use super::movegen::{self, Placement};
+use super::spin::SpinDetection;
+use super::tetronimo::{Tetromino, TetronimoState};
+use super::transform::Point;
+use super::Result;
+
+/// Names of the weights, in the order of `Weights::to_vec`.
+pub const WEIGHT_NAMES: [&'static str; 5] = ["aggregate_height",
+                                             "holes",
+                                             "bumpiness",
+                                             "wells",
+                                             "lines"];
+
+/// How much each feature of a board is worth. Positive weights are
+/// rewards, negative ones penalties.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    /// Sum of the column heights.
+    pub aggregate_height: f64,
+    /// Empty cells with a filled cell somewhere above them.
+    pub holes: f64,
+    /// Sum of height differences between neighbouring columns.
+    pub bumpiness: f64,
+    /// Sum of well depths, counting the walls as full columns.
+    pub wells: f64,
+    /// Lines cleared by the placement.
+    pub lines: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            aggregate_height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.05,
+            lines: 0.76,
+        }
+    }
+}
+
+impl Weights {
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![self.aggregate_height, self.holes, self.bumpiness, self.wells, self.lines]
+    }
+
+    /// Inverse of `to_vec`, `values` must hold one number per weight.
+    pub fn from_slice(values: &[f64]) -> Weights {
+        Weights {
+            aggregate_height: values[0],
+            holes: values[1],
+            bumpiness: values[2],
+            wells: values[3],
+            lines: values[4],
+        }
+    }
+
+    pub fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        for (name, value) in WEIGHT_NAMES.iter().zip(self.to_vec()) {
+            table.insert(name.to_string(), toml::Value::Float(value));
+        }
+        toml::Value::Table(table)
+    }
+
+    pub fn from_toml(value: &toml::Value) -> Result<Weights> {
+        let mut values = Vec::with_capacity(WEIGHT_NAMES.len());
+        for name in &WEIGHT_NAMES {
+            let value = try!(value.lookup(name)
+                .and_then(|v| v.as_float().or(v.as_integer().map(|i| i as f64)))
+                .ok_or_else(|| {
+                    Error::BadWeights(ParseError::new(format!("missing {:?}", name)))
+                }));
+            values.push(value);
+        }
+        Ok(Weights::from_slice(&values))
+    }
+
+    /// Reads the best weights from a file written by `testris tune`,
+    /// the first entry of its `[[weights]]` list.
+    pub fn load(path: &Path) -> Result<Weights> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| Error::from(e).in_file(path)));
+        Weights::parse(&text).map_err(|e| e.in_file(path))
+    }
+
+    pub fn parse(text: &str) -> Result<Weights> {
+        let mut parser = toml::Parser::new(text);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(Error::BadWeights(ParseError::at(line + 1, col + 1, e.desc.clone())));
+            }
+        };
+        let best = try!(table.get("weights")
+            .and_then(|v| v.as_slice())
+            .and_then(|list| list.first())
+            .ok_or_else(|| Error::BadWeights(ParseError::new("no [[weights]] entries"))));
+        Weights::from_toml(best)
+    }
+
+    pub fn score(&self, features: &Features) -> f64 {
+        self.aggregate_height * features.aggregate_height as f64 +
+        self.holes * features.holes as f64 + self.bumpiness * features.bumpiness as f64 +
+        self.wells * features.wells as f64 + self.lines * features.lines as f64
+    }
+}
+
+/// What the heuristic looks at, measured on a board after a placement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    pub aggregate_height: usize,
+    pub holes: usize,
+    pub bumpiness: usize,
+    pub wells: usize,
+    pub lines: usize,
+}
+
+impl Features {
+    pub fn measure(bits: &BitBoard, lines: usize) -> Features {
+        let heights: Vec<usize> = (0..bits.width()).map(|x| bits.column_height(x)).collect();
+        let filled: usize = bits.count();
+        let aggregate_height: usize = heights.iter().sum();
+
+        let mut bumpiness = 0;
+        let mut wells = 0;
+        for x in 0..heights.len() {
+            if x + 1 < heights.len() {
+                bumpiness += (heights[x] as isize - heights[x + 1] as isize).abs() as usize;
+            }
+            let left = if x == 0 { bits.height() } else { heights[x - 1] };
+            let right = if x + 1 == heights.len() { bits.height() } else { heights[x + 1] };
+            let rim = left.min(right);
+            if rim > heights[x] {
+                wells += rim - heights[x];
+            }
+        }
+        Features {
+            aggregate_height: aggregate_height,
+            holes: aggregate_height - filled,
+            bumpiness: bumpiness,
+            wells: wells,
+            lines: lines,
+        }
+    }
+}
+
+/// The board after locking a placement, and how many lines it cleared.
+fn after(board: &GameBoard, placement: &Placement) -> (GameBoard, usize) {
+    let mut next = board.clone();
+    next.add_blocks(&placement.piece.blocks());
+    let lines = next.wipe_full_rows();
+    (next, lines)
+}
+
+/// Picks the placement of `piece` with the best heuristic score, looking
+/// at where each of the `preview` pieces could go afterwards.
+pub fn best_placement(board: &GameBoard,
+                      piece: &Tetromino,
+                      preview: &[Tetromino],
+                      weights: &Weights,
+                      detection: SpinDetection)
+                      -> Option<Placement> {
+    let mut best: Option<(f64, Placement)> = None;
+    for placement in movegen::placements(board, piece, detection) {
+        let (next, lines) = after(board, &placement);
+        let score = weights.lines * lines as f64 +
+                    search(&next, lines, preview, weights, detection);
+        if best.as_ref().map(|&(b, _)| score > b).unwrap_or(true) {
+            best = Some((score, placement));
+        }
+    }
+    best.map(|(_, placement)| placement)
+}
+
+/// Best score reachable by placing the `preview` pieces in order.
+fn search(board: &GameBoard,
+          lines: usize,
+          preview: &[Tetromino],
+          weights: &Weights,
+          detection: SpinDetection)
+          -> f64 {
+    let piece = match preview.first() {
+        Some(piece) => piece,
+        None => {
+            let mut features = Features::measure(board.bits(), lines);
+            // Line rewards were already counted as each piece was placed
+            features.lines = 0;
+            return weights.score(&features);
+        }
+    };
+    movegen::placements(board, piece, detection)
+        .iter()
+        .map(|placement| {
+            let (next, cleared) = after(board, placement);
+            weights.lines * cleared as f64 +
+            search(&next, cleared, &preview[1..], weights, detection)
+        })
+        .fold(::std::f64::NEG_INFINITY, f64::max)
+}
+
+/// The next `lookahead` pieces, each where it will spawn.
+fn spawned_preview(game: &Game, lookahead: usize) -> Vec<Tetromino> {
+    (0..lookahead)
+        .filter_map(|i| game.preview(i))
+        .map(|mut next| {
+            next.put(game.spawn_point());
+            next
+        })
+        .collect()
+}
+
+/// Places the active piece straight away where `best_placement` puts it,
+/// spawning it first if needed. Returns false once nothing can be placed.
+pub fn play_piece(game: &mut Game, weights: &Weights, lookahead: usize) -> bool {
+    if game.is_over() {
+        return false;
+    }
+    if game.active_piece().state != TetronimoState::Falling {
+        // Spawns the next piece without letting any time pass
+        game.on_update(0f64);
+        if game.is_over() {
+            return false;
+        }
+    }
+    match suggest(game, weights, lookahead) {
+        Some(placement) => game.place(&placement.piece, placement.kick).is_ok(),
+        None => false,
+    }
+}
+
+/// Where the heuristic would lock the active piece, taking the next
+/// `lookahead` pieces into account.
+pub fn suggest(game: &Game, weights: &Weights, lookahead: usize) -> Option<Placement> {
+    let preview = spawned_preview(game, lookahead);
+    best_placement(game.board(),
+                   game.active_piece(),
+                   &preview,
+                   weights,
+                   game.ruleset().spin_detection)
+}
+
+/// Plays a `Game` by pressing and releasing keys, like a person would.
+pub struct Bot {
+    weights: Weights,
+    /// How many preview pieces to take into account.
+    lookahead: usize,
+    /// Seconds between key presses.
+    delay: f64,
+    timer: f64,
+    /// Cells of the chosen placement for the current piece.
+    target: Option<[Point; 4]>,
+    pieces: usize,
+    held: Option<Command>,
+}
+
+impl Bot {
+    /// A bot pressing at most `speed` keys per second.
+    pub fn new(weights: Weights, lookahead: usize, speed: f64) -> Self {
+        Bot {
+            weights: weights,
+            lookahead: lookahead,
+            delay: if speed > 0f64 { 1f64 / speed } else { 0f64 },
+            timer: 0f64,
+            target: None,
+            pieces: 0,
+            held: None,
+        }
+    }
+
+    /// Presses or releases at most one key, call before `Game::on_update`.
+    pub fn on_update(&mut self, game: &mut Game, dt: f64) {
+        if game.is_over() || game.active_piece().state != TetronimoState::Falling {
+            return;
+        }
+        if game.statistics().pieces() != self.pieces {
+            self.pieces = game.statistics().pieces();
+            self.target = None;
+            self.held = None;
+        }
+
+        // Soft drops are held until the piece lands, everything else is a tap
+        if let Some(command) = self.held {
+            let piece = game.active_piece().clone();
+            let landed = game.board().drop_piece(&piece).origin() == piece.origin();
+            if command != Command::DownFast || landed {
+                game.on_release(command);
+                self.held = None;
+            }
+            return;
+        }
+
+        self.timer += dt;
+        if self.timer < self.delay {
+            return;
+        }
+        self.timer = 0f64;
+
+        if let Some(command) = self.next_command(game) {
+            game.on_press(command);
+            self.held = Some(command);
+        }
+    }
+
+    /// First key on the way to the target, choosing one if needed.
+    fn next_command(&mut self, game: &Game) -> Option<Command> {
+        let detection = game.ruleset().spin_detection;
+        let piece = game.active_piece().clone();
+        if let Some(target) = self.target {
+            let path = movegen::placements(game.board(), &piece, detection)
+                .into_iter()
+                .find(|p| same_cells(&p.cells(), &target))
+                .map(|p| p.path);
+            if let Some(path) = path {
+                return path.first().cloned();
+            }
+        }
+
+        suggest(game, &self.weights, self.lookahead).and_then(|placement| {
+            self.target = Some(placement.cells());
+            placement.path.first().cloned()
+        })
+    }
+}
+
+fn same_cells(a: &[Point; 4], b: &[Point; 4]) -> bool {
+    a.iter().all(|p| b.contains(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitboard::BitBoard;
+    use game::Game;
+    use mode::Mode;
+    use rules::Ruleset;
+
+    #[test]
+    fn measure_features() {
+        let mut bits = BitBoard::new(4, 6);
+        // Heights 3, 0, 1, 2 with one hole in the first column
+        bits.set(0, 0);
+        bits.set(0, 2);
+        bits.set(2, 0);
+        bits.set(3, 0);
+        bits.set(3, 1);
+        let features = Features::measure(&bits, 2);
+        assert_eq!(6, features.aggregate_height);
+        assert_eq!(1, features.holes);
+        assert_eq!(3 + 1 + 1, features.bumpiness);
+        // Column 1 sits between heights 3 and 1
+        assert_eq!(1, features.wells);
+        assert_eq!(2, features.lines);
+    }
+
+    #[test]
+    fn hints_follow_the_active_piece() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 4);
+        game.on_update(0f64);
+        assert!(game.hint().is_none());
+        game.toggle_hint();
+        let expected = suggest(&game, &Weights::default(), 1).unwrap().cells();
+        assert_eq!(expected, game.hint().unwrap().cells());
+        assert_eq!(TetronimoState::Hint, game.hint().unwrap().state);
+
+        // A new piece gets a new hint
+        game.on_press(Command::Hold);
+        game.on_update(0f64);
+        game.on_release(Command::Hold);
+        assert_eq!(game.active_piece().shape(), game.hint().unwrap().shape());
+
+        game.toggle_hint();
+        assert!(game.hint().is_none());
+    }
+
+    #[test]
+    fn bot_clears_lines() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 11);
+        let mut bot = Bot::new(Weights::default(), 0, 0f64);
+        for _ in 0..(60 * 60) {
+            bot.on_update(&mut game, 1f64 / 60f64);
+            game.on_update(1f64 / 60f64);
+            if game.is_over() || game.lines() >= 10 {
+                break;
+            }
+        }
+        assert!(!game.is_over());
+        assert!(game.lines() >= 10, "Only cleared {} lines", game.lines());
+    }
+}
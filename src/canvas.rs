@@ -0,0 +1,227 @@
+use png::{self, HasParameters};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::cli;
+use super::game::Game;
+use super::replay::Replay;
+use super::Result;
+
+/// What the window is cleared to before a frame is drawn.
+pub const BACKGROUND: [f32; 4] = [0.5; 4];
+
+/// A filled rectangle, `area` being x, y, width and height in window
+/// coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quad {
+    pub color: [f32; 4],
+    pub area: [f64; 4],
+}
+
+/// An RGBA image drawn without a window, for screenshots and tests.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn channel(c: f32) -> u8 {
+    (c * 255f32).round().max(0f32).min(255f32) as u8
+}
+
+impl Canvas {
+    /// An opaque canvas filled with `background`.
+    pub fn new(size: [u32; 2], background: [f32; 4]) -> Canvas {
+        let pixel = [channel(background[0]), channel(background[1]), channel(background[2]), 255];
+        let count = size[0] as usize * size[1] as usize;
+        let mut pixels = Vec::with_capacity(count * 4);
+        for _ in 0..count {
+            pixels.extend_from_slice(&pixel);
+        }
+        Canvas {
+            width: size[0],
+            height: size[1],
+            pixels: pixels,
+        }
+    }
+
+    /// A frame of the game as it would appear in its window, resized
+    /// by `scale`.
+    pub fn of_game(game: &Game, scale: f64) -> Canvas {
+        let size = game.window_size();
+        let scaled = |length: u32| ((length as f64 * scale).round() as u32).max(1);
+        let mut canvas = Canvas::new([scaled(size[0]), scaled(size[1])], BACKGROUND);
+        for quad in game.quads() {
+            let area = quad.area;
+            canvas.fill(&Quad {
+                color: quad.color,
+                area: [area[0] * scale, area[1] * scale, area[2] * scale, area[3] * scale],
+            });
+        }
+        canvas
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Every pixel as RGBA, row by row from the top.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Blends the quad over every pixel whose center it covers.
+    pub fn fill(&mut self, quad: &Quad) {
+        let span = |start: f64, length: f64, limit: u32| {
+            let first = (start - 0.5).ceil().max(0f64).min(limit as f64) as u32;
+            let end = (start + length - 0.5).ceil().max(0f64).min(limit as f64) as u32;
+            (first, end.max(first))
+        };
+        let (x0, x1) = span(quad.area[0], quad.area[2], self.width);
+        let (y0, y1) = span(quad.area[1], quad.area[3], self.height);
+        let alpha = quad.color[3].max(0f32).min(1f32);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = (y as usize * self.width as usize + x as usize) * 4;
+                for c in 0..3 {
+                    let below = self.pixels[i + c] as f32 / 255f32;
+                    self.pixels[i + c] = channel(quad.color[c] * alpha + below * (1f32 - alpha));
+                }
+            }
+        }
+    }
+
+    pub fn write_png<W: Write>(&self, w: W) -> Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = try!(encoder.write_header().map_err(io::Error::from));
+        try!(writer.write_image_data(&self.pixels).map_err(io::Error::from));
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = try!(File::create(path));
+        self.write_png(BufWriter::new(file))
+    }
+}
+
+/// `testris snapshot REPLAY [--pieces N] [--scale X] [--output FILE]`
+/// saves a PNG of a recorded game, by default as it ended.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let path = match args.first() {
+        Some(path) if !path.starts_with("--") => PathBuf::from(path),
+        _ => {
+            println!("usage: testris snapshot REPLAY [--pieces N] [--scale X] [--output FILE]");
+            return Ok(());
+        }
+    };
+    let replay = try!(Replay::load(&path));
+    let game = match cli::flag_value(args, "--pieces") {
+        Some(_) => replay.play_until(try!(cli::number_flag(args, "--pieces", 0usize))),
+        None => replay.play(),
+    };
+    let scale = try!(cli::number_flag(args, "--scale", 1f64));
+    let output = match cli::flag_value(args, "--output") {
+        Some(output) => PathBuf::from(output),
+        None => path.with_extension("png"),
+    };
+    try!(Canvas::of_game(&game, scale).save(&output));
+    println!("Saved {}", output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bot::{self, Weights};
+    use game::Game;
+    use mode::Mode;
+    use rules::Ruleset;
+    use std::env;
+    use std::io::Read;
+
+    #[test]
+    fn quads_blend_over_the_background() {
+        let mut canvas = Canvas::new([4, 4], BACKGROUND);
+        canvas.fill(&Quad {
+            color: [1f32, 0f32, 0f32, 1f32],
+            area: [1f64, 1f64, 2f64, 2f64],
+        });
+        canvas.fill(&Quad {
+            color: [0f32, 0f32, 0f32, 0.5f32],
+            area: [2f64, 0f64, 10f64, 2f64],
+        });
+        assert_eq!([128, 128, 128, 255], canvas.pixel(0, 0));
+        assert_eq!([255, 0, 0, 255], canvas.pixel(1, 1));
+        assert_eq!([128, 0, 0, 255], canvas.pixel(2, 1));
+        assert_eq!([64, 64, 64, 255], canvas.pixel(3, 0));
+        assert_eq!([128, 128, 128, 255], canvas.pixel(0, 3));
+    }
+
+    #[test]
+    fn games_render_like_the_window() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 3);
+        game.on_update(0f64);
+        let canvas = Canvas::of_game(&game, 1f64);
+        assert_eq!(game.window_size(), canvas.size());
+        // The last opaque quad over a point is what shows there
+        let quads = game.quads();
+        for (i, quad) in quads.iter().enumerate() {
+            let (x, y) = (quad.area[0] + quad.area[2] / 2f64, quad.area[1] + quad.area[3] / 2f64);
+            let covered = quads[i + 1..].iter().any(|q| {
+                x >= q.area[0] && x < q.area[0] + q.area[2] && y >= q.area[1] &&
+                y < q.area[1] + q.area[3]
+            });
+            let inside = x >= 0f64 && y >= 0f64 && x < canvas.size()[0] as f64 &&
+                         y < canvas.size()[1] as f64;
+            if quad.color[3] == 1f32 && !covered && inside {
+                let pixel = canvas.pixel(x as u32, y as u32);
+                let expected =
+                    [channel(quad.color[0]), channel(quad.color[1]), channel(quad.color[2])];
+                assert_eq!(expected, [pixel[0], pixel[1], pixel[2]]);
+            }
+        }
+    }
+
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        let (info, mut reader) = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        (info.width, info.height, pixels)
+    }
+
+    /// Compares against a frame checked in as testdata/marathon-3.png.
+    /// Pixels are compared rather than files, since encoders compress
+    /// differently. Run with TESTRIS_BLESS set to write a new reference
+    /// after a deliberate change to the look of the game.
+    #[test]
+    fn games_render_like_the_reference() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 3);
+        for _ in 0..12 {
+            assert!(bot::play_piece(&mut game, &Weights::default(), 0));
+        }
+        game.on_update(0f64);
+        let mut png = Vec::new();
+        Canvas::of_game(&game, 1f64).write_png(&mut png).unwrap();
+        // Read when the test runs, so a blessed reference is the one used
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/marathon-3.png");
+        if env::var_os("TESTRIS_BLESS").is_some() {
+            File::create(&path).unwrap().write_all(&png).unwrap();
+        }
+        let mut reference = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut reference).unwrap();
+        let reference = decode(&reference);
+        let (width, height, pixels) = decode(&png);
+        assert_eq!((reference.0, reference.1), (width, height));
+        // Reports the first pixel off rather than dumping both images
+        let mismatch = pixels.iter().zip(&reference.2).position(|(a, b)| a != b);
+        assert_eq!(None, mismatch.map(|i| ((i / 4) as u32 % width, (i / 4) as u32 / width)));
+    }
+}
@@ -0,0 +1,43 @@
+use std::str::FromStr;
+
+use super::error::{Error, ParseError};
+use super::Result;
+
+/// Value following a command line flag, like `--rules allspin`.
+pub fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+/// Number following a flag, or `default` if the flag is not given.
+pub fn number_flag<T: FromStr>(args: &[String], flag: &str, default: T) -> Result<T> {
+    match flag_value(args, flag) {
+        Some(value) => {
+            value.parse::<T>().map_err(|_| {
+                Error::Config(ParseError::new(format!("{}: expected a number, not {:?}",
+                                                      flag,
+                                                      value)))
+            })
+        }
+        None => Ok(default),
+    }
+}
+
+#[test]
+fn flag_values() {
+    let args: Vec<String> = vec!["--mode".to_string(), "sprint".to_string(), "--seed".to_string()];
+    assert_eq!(Some("sprint"), flag_value(&args, "--mode"));
+    assert_eq!(None, flag_value(&args, "--seed"));
+    assert_eq!(None, flag_value(&args, "--rules"));
+}
+
+#[test]
+fn number_flags() {
+    let args: Vec<String> = vec!["--games".to_string(), "8".to_string(), "--seed".to_string(),
+                                 "x".to_string()];
+    assert_eq!(8, number_flag(&args, "--games", 1usize).unwrap());
+    assert_eq!(3, number_flag(&args, "--threads", 3usize).unwrap());
+    assert!(number_flag(&args, "--seed", 0u32).is_err());
+}
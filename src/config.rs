@@ -0,0 +1,238 @@
+use toml;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::board::Dimensions;
+use super::error::{Error, ParseError};
+use super::mode::Mode;
+use super::rules::Ruleset;
+use super::Result;
+
+/// Where the config file lives, following the XDG base directory spec.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::home_dir().map(|home| home.join(".config")),
+    };
+    dir.map(|dir| dir.join("testris").join("config.toml"))
+}
+
+/// Settings read from `config.toml`, command line flags override them.
+///
+/// ```toml
+/// [game]
+/// mode = "sprint"
+/// rules = "allspin"
+/// seed = 42
+///
+/// [board]
+/// width = 10
+/// height = 20
+/// hidden = 2
+///
+/// [files]
+/// records = "/home/me/testris/records.toml"
+/// stats = "testris-stats.json"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub mode: Mode,
+    pub ruleset: Ruleset,
+    pub seed: Option<u32>,
+    pub dimensions: Dimensions,
+    pub records: Option<PathBuf>,
+    pub stats: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mode: Mode::Marathon,
+            ruleset: Ruleset::guideline(),
+            seed: None,
+            dimensions: Dimensions::standard(),
+            records: None,
+            stats: PathBuf::from("testris-stats.json"),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| Error::from(e).in_file(path)));
+        Config::parse(&text).map_err(|e| e.in_file(path))
+    }
+
+    /// Loads the default config file, if there is one.
+    pub fn load_default() -> Result<Config> {
+        match default_path() {
+            Some(ref path) if path.exists() => Config::load(path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config> {
+        let mut parser = toml::Parser::new(text);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(Error::Config(ParseError::at(line + 1, col + 1, e.desc.clone())));
+            }
+        };
+
+        let mut config = Config::default();
+        for (section, value) in &table {
+            let keys = try!(value.as_table().ok_or_else(|| {
+                invalid(text, None, section, "expected a section")
+            }));
+            for (key, value) in keys {
+                let bad = |message: &str| invalid(text, Some(section), key, message);
+                match (section.as_str(), key.as_str()) {
+                    ("game", "mode") => {
+                        config.mode = try!(value.as_str()
+                            .and_then(Mode::by_name)
+                            .ok_or_else(|| bad("expected marathon or sprint")));
+                    }
+                    ("game", "rules") => {
+                        config.ruleset = try!(value.as_str()
+                            .and_then(Ruleset::by_name)
+                            .ok_or_else(|| bad("expected guideline or allspin")));
+                    }
+                    ("game", "seed") => {
+                        let seed = try!(value.as_integer()
+                            .and_then(|i| if i >= 0 && i <= u32::max_value() as i64 {
+                                Some(i as u32)
+                            } else {
+                                None
+                            })
+                            .ok_or_else(|| bad("expected a number")));
+                        config.seed = Some(seed);
+                    }
+                    ("board", "width") | ("board", "height") | ("board", "hidden") => {
+                        let n = try!(size(value).ok_or_else(|| bad("expected a number")));
+                        match key.as_str() {
+                            "width" => config.dimensions.width = n,
+                            "height" => config.dimensions.height = n,
+                            _ => config.dimensions.hidden = n,
+                        }
+                    }
+                    ("files", "records") => {
+                        config.records = Some(PathBuf::from(try!(value.as_str()
+                            .ok_or_else(|| bad("expected a path")))));
+                    }
+                    ("files", "stats") => {
+                        config.stats = PathBuf::from(try!(value.as_str()
+                            .ok_or_else(|| bad("expected a path"))));
+                    }
+                    _ => return Err(bad("unknown setting")),
+                }
+            }
+        }
+        if let Err(e) = config.dimensions.check() {
+            let line = position_of(text, None, "board").map(|(line, _)| line).unwrap_or(1);
+            return Err(Error::Config(ParseError::at(line, 1, e.to_string())));
+        }
+        Ok(config)
+    }
+}
+
+fn size(value: &toml::Value) -> Option<usize> {
+    value.as_integer().and_then(|i| if i >= 0 { Some(i as usize) } else { None })
+}
+
+/// A config error pointing at the line where `key` was set.
+fn invalid(text: &str, section: Option<&str>, key: &str, message: &str) -> Error {
+    let message = match section {
+        Some(section) => format!("{}.{}: {}", section, key, message),
+        None => format!("{}: {}", key, message),
+    };
+    match position_of(text, section, key) {
+        Some((line, column)) => Error::Config(ParseError::at(line, column, message)),
+        None => Error::Config(ParseError::new(message)),
+    }
+}
+
+/// Finds `key = ...` inside `[section]`, or the `[key]` header itself.
+fn position_of(text: &str, section: Option<&str>, key: &str) -> Option<(usize, usize)> {
+    let mut current: Option<&str> = None;
+    for (number, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        let column = line.len() - line.trim_left().len() + 1;
+        if trimmed.starts_with('[') {
+            let name = trimmed.trim_matches(|c| c == '[' || c == ']').trim();
+            if section.is_none() && name == key {
+                return Some((number + 1, column));
+            }
+            current = Some(name);
+        } else if current == section && trimmed.starts_with(key) &&
+                  trimmed[key.len()..].trim_left().starts_with('=') {
+            return Some((number + 1, column));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Dimensions;
+    use error::{Error, ParseError};
+    use mode::Mode;
+    use rules::Ruleset;
+    use std::path::PathBuf;
+
+    #[test]
+    fn empty_is_default() {
+        assert_eq!(Config::default(), Config::parse("").unwrap());
+    }
+
+    #[test]
+    fn read_settings() {
+        let config = Config::parse("[game]\nmode = \"sprint\"\nrules = \"allspin\"\nseed = 4\n\
+                                    [files]\nstats = \"out.json\"\n")
+            .unwrap();
+        assert_eq!(Mode::Sprint, config.mode);
+        assert_eq!(Ruleset::all_spin(), config.ruleset);
+        assert_eq!(Some(4), config.seed);
+        assert_eq!(PathBuf::from("out.json"), config.stats);
+    }
+
+    #[test]
+    fn board_dimensions() {
+        let config = Config::parse("[board]\nwidth = 4\nheight = 8\n").unwrap();
+        assert_eq!(Dimensions {
+                       width: 4,
+                       height: 8,
+                       hidden: 2,
+                   },
+                   config.dimensions);
+        match Config::parse("[game]\nseed = 1\n[board]\nwidth = 41\n") {
+            Err(Error::Config(ParseError { position: Some((3, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        match Config::parse("[game]\nmode = \"sprint\"\n  seed = -1\n") {
+            Err(Error::Config(ParseError { position: Some((3, 3)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match Config::parse("[game]\nmode = \n") {
+            Err(Error::Config(ParseError { position: Some((2, _)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match Config::parse("[sound]\nvolume = 3\n") {
+            Err(Error::Config(ParseError { position: Some((2, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use super::bitboard::BitBoard;
+use super::board::Dimensions;
+use super::game::Game;
+use super::input::Command;
+use super::mode::Mode;
+use super::movegen::{self, Placement};
+use super::rules::Ruleset;
+use super::tetronimo::{Shape, Tetromino, TetronimoState};
+use super::Result;
+
+/// Seconds that pass on every `Action::Command` or `Action::Wait` step.
+pub const FRAME: f64 = 1f64 / 60f64;
+/// Values of `Shape as usize` range below this.
+const SHAPES: usize = 7;
+
+/// What an agent can do in one step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Let one frame pass without touching anything.
+    Wait,
+    /// Press a key for one frame and let go of it again.
+    Command(Command),
+    /// Lock the active piece at `Observation::placements[i]` straight away.
+    Place(usize),
+}
+
+/// Everything an agent gets to see.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// Filled cells, hidden rows included, row 0 at the bottom.
+    pub board: BitBoard,
+    /// The falling piece, `None` once the game is over.
+    pub active: Option<Tetromino>,
+    pub hold: Option<Shape>,
+    /// Upcoming pieces, next one first.
+    pub queue: Vec<Shape>,
+    /// Where the active piece can be locked, for `Action::Place`.
+    pub placements: Vec<Placement>,
+}
+
+impl Observation {
+    /// The board as rows of cells, bottom row first.
+    pub fn grid(&self) -> Vec<Vec<bool>> {
+        (0..self.board.height())
+            .map(|y| {
+                (0..self.board.width())
+                    .map(|x| self.board.is_occupied(x as i32, y as i32))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A flat vector for feeding a network: the grid, then one-hot
+    /// encodings of the active shape, its orientation, the hold and
+    /// every queued shape, then the active piece's column and row.
+    pub fn to_vec(&self) -> Vec<f32> {
+        let mut v: Vec<f32> = Vec::new();
+        for row in self.grid() {
+            v.extend(row.iter().map(|&filled| if filled { 1f32 } else { 0f32 }));
+        }
+        let one_hot = |v: &mut Vec<f32>, size: usize, index: Option<usize>| {
+            v.extend((0..size).map(|i| if Some(i) == index { 1f32 } else { 0f32 }));
+        };
+        one_hot(&mut v, SHAPES, self.active.as_ref().map(|p| p.shape() as usize));
+        one_hot(&mut v, 4, self.active.as_ref().map(|p| p.orientation() as usize));
+        one_hot(&mut v, SHAPES, self.hold.map(|s| s as usize));
+        for &shape in &self.queue {
+            one_hot(&mut v, SHAPES, Some(shape as usize));
+        }
+        let origin = self.active.as_ref().map(|p| p.origin());
+        v.push(origin.map(|p| p.x as f32).unwrap_or(-1f32));
+        v.push(origin.map(|p| p.y as f32).unwrap_or(-1f32));
+        v
+    }
+}
+
+/// Extra facts about a step that are not part of the observation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Info {
+    /// Lines cleared by this step.
+    pub cleared: usize,
+    pub lines: usize,
+    pub score: u64,
+    pub pieces: usize,
+    /// The seed of the game this step belongs to.
+    pub seed: u32,
+}
+
+/// A gym style environment around a headless `Game`.
+pub struct Env {
+    mode: Mode,
+    ruleset: Ruleset,
+    dimensions: Dimensions,
+    preview: usize,
+    seed: u32,
+    game: Game,
+    placements: Vec<Placement>,
+}
+
+impl Env {
+    /// An environment showing `preview` upcoming pieces. Call `reset`
+    /// before the first step.
+    pub fn new(mode: Mode,
+               ruleset: Ruleset,
+               dimensions: Dimensions,
+               preview: usize)
+               -> Result<Env> {
+        Ok(Env {
+            mode: mode,
+            ruleset: ruleset,
+            dimensions: dimensions,
+            preview: preview,
+            seed: 0,
+            game: try!(Game::with_dimensions(mode, ruleset, 0, dimensions)),
+            placements: Vec::new(),
+        })
+    }
+
+    /// Starts a new game, the same seed always deals the same pieces.
+    pub fn reset(&mut self, seed: u32) -> Observation {
+        // The dimensions were checked in `new`
+        let game = Game::with_dimensions(self.mode, self.ruleset, seed, self.dimensions);
+        self.game = game.unwrap();
+        self.seed = seed;
+        self.spawn();
+        self.observe()
+    }
+
+    /// Plays one action. The reward is the score it earned, and the
+    /// game is done once it is topped out or its goal is reached.
+    ///
+    /// Panics if a placement index is not below `placements().len()`.
+    pub fn step(&mut self, action: Action) -> (Observation, f64, bool, Info) {
+        let (score, lines) = (self.game.score(), self.game.lines());
+        match action {
+            Action::Wait => self.game.on_update(FRAME),
+            Action::Command(command) => {
+                self.game.on_press(command);
+                self.game.on_update(FRAME);
+                self.game.on_release(command);
+            }
+            Action::Place(i) => {
+                let placement = &self.placements[i];
+                if let Err(e) = self.game.place(&placement.piece, placement.kick) {
+                    error!("Placement {} was not legal: {}", i, e);
+                }
+            }
+        }
+        self.spawn();
+
+        let info = Info {
+            cleared: self.game.lines() - lines,
+            lines: self.game.lines(),
+            score: self.game.score(),
+            pieces: self.game.statistics().pieces(),
+            seed: self.seed,
+        };
+        let reward = (self.game.score() - score) as f64;
+        (self.observe(), reward, self.game.is_over(), info)
+    }
+
+    /// Where the active piece can be locked right now.
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    /// Seed of the current game.
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Brings in the next piece without letting time pass, so every
+    /// observation has a piece to move.
+    fn spawn(&mut self) {
+        if !self.game.is_over() && self.game.active_piece().state != TetronimoState::Falling {
+            self.game.on_update(0f64);
+        }
+        self.placements = if self.game.is_over() {
+            Vec::new()
+        } else {
+            movegen::placements(self.game.board(),
+                                self.game.active_piece(),
+                                self.ruleset.spin_detection)
+        };
+    }
+
+    fn observe(&self) -> Observation {
+        let active = if self.game.is_over() {
+            None
+        } else {
+            Some(self.game.active_piece().clone())
+        };
+        Observation {
+            board: self.game.board().bits().clone(),
+            active: active,
+            hold: self.game.held(),
+            queue: (0..self.preview)
+                .filter_map(|i| self.game.preview(i))
+                .map(|p| p.shape())
+                .collect(),
+            placements: self.placements.clone(),
+        }
+    }
+}
+
+/// Several environments stepped together, for training on batches.
+/// A game that is done starts over on its own with a new seed.
+pub struct BatchEnv {
+    envs: Vec<Env>,
+    next_seed: u32,
+}
+
+impl BatchEnv {
+    pub fn new(count: usize,
+               mode: Mode,
+               ruleset: Ruleset,
+               dimensions: Dimensions,
+               preview: usize)
+               -> Result<BatchEnv> {
+        let mut envs = Vec::with_capacity(count);
+        for _ in 0..count {
+            envs.push(try!(Env::new(mode, ruleset, dimensions, preview)));
+        }
+        Ok(BatchEnv {
+            envs: envs,
+            next_seed: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    /// Restarts every game, seeding them `seed`, `seed + 1` and so on.
+    pub fn reset(&mut self, seed: u32) -> Vec<Observation> {
+        self.next_seed = seed.wrapping_add(self.envs.len() as u32);
+        self.envs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, env)| env.reset(seed.wrapping_add(i as u32)))
+            .collect()
+    }
+
+    /// Steps every game with its own action. When a game is done, its
+    /// `Info` describes the finished game but its observation is
+    /// already that of the fresh one.
+    pub fn step(&mut self, actions: &[Action]) -> Vec<(Observation, f64, bool, Info)> {
+        assert_eq!(self.envs.len(),
+                   actions.len(),
+                   "Expected one action per environment");
+        let mut results = Vec::with_capacity(actions.len());
+        for (env, &action) in self.envs.iter_mut().zip(actions) {
+            let (mut observation, reward, done, info) = env.step(action);
+            if done {
+                observation = env.reset(self.next_seed);
+                self.next_seed = self.next_seed.wrapping_add(1);
+            }
+            results.push((observation, reward, done, info));
+        }
+        results
+    }
+
+    pub fn envs(&self) -> &[Env] {
+        &self.envs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Dimensions;
+    use input::Command;
+    use mode::Mode;
+    use rules::Ruleset;
+
+    fn env() -> Env {
+        Env::new(Mode::Marathon, Ruleset::guideline(), Dimensions::standard(), 5).unwrap()
+    }
+
+    #[test]
+    fn reset_shows_a_piece_and_the_queue() {
+        let mut env = env();
+        let observation = env.reset(3);
+        let active = observation.active.clone().unwrap();
+        assert_eq!(env.game().preview(0).unwrap().shape(), observation.queue[0]);
+        assert_eq!(5, observation.queue.len());
+        assert_eq!(None, observation.hold);
+        assert!(observation.board.is_empty());
+        assert_eq!(22, observation.grid().len());
+        assert!(!observation.placements.is_empty());
+        assert_eq!(22 * 10 + 7 + 4 + 7 + 5 * 7 + 2, observation.to_vec().len());
+
+        let again = env.reset(3);
+        assert_eq!(active.cells(), again.active.unwrap().cells());
+        assert_eq!(observation.queue, again.queue);
+    }
+
+    #[test]
+    fn commands_and_placements() {
+        let mut env = env();
+        let observation = env.reset(1);
+        let (held, _, _, _) = env.step(Action::Command(Command::Hold));
+        assert_eq!(observation.active.map(|p| p.shape()), held.hold);
+        assert_eq!(Some(observation.queue[0]), held.active.map(|p| p.shape()));
+
+        let (placed, reward, done, info) = env.step(Action::Place(0));
+        assert!(!done);
+        assert_eq!(0f64, reward);
+        assert_eq!(1, info.pieces);
+        assert_eq!(4, placed.board.count());
+        assert_eq!(Some(observation.queue[1]), placed.active.map(|p| p.shape()));
+    }
+
+    #[test]
+    fn batches_restart_finished_games() {
+        let mut batch =
+            BatchEnv::new(2, Mode::Marathon, Ruleset::guideline(), Dimensions::standard(), 1)
+                .unwrap();
+        batch.reset(10);
+        let mut finished = None;
+        for _ in 0..200 {
+            // Stacking everything in the first spot tops out quickly
+            let results = batch.step(&[Action::Place(0), Action::Wait]);
+            assert!(!results[1].2);
+            if results[0].2 {
+                finished = Some(results[0].clone());
+                break;
+            }
+        }
+        let (observation, _, _, info) = finished.expect("Game never ended");
+        assert_eq!(10, info.seed);
+        assert!(observation.board.is_empty());
+        assert_eq!(12, batch.envs()[0].seed());
+    }
+}
@@ -1,17 +1,70 @@
 use std::error;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
 
-/// Error type for dealing with problems
-/// with the encoder.
-#[derive(PartialEq, Eq, Debug)]
+use super::transform::Point;
+
+/// Error type for everything that can go wrong
+/// outside of normal play.
+#[derive(Debug)]
 pub enum Error {
+    /// A point that is not on the board.
     OutOfBounds,
+    /// A piece that leaves the board or overlaps the stack at this point.
+    IllegalPlacement(Point),
+    InvalidDimensions {
+        width: usize,
+        height: usize,
+        hidden: usize,
+    },
+    Io(io::Error),
+    /// Any other error, caused while reading or writing this file.
+    File(PathBuf, Box<Error>),
+    Config(ParseError),
+    BadRecords(ParseError),
+    UnsupportedRecords(i64),
+    BadReplay(ParseError),
+    UnsupportedReplay(u32),
+    BadWeights(ParseError),
+    BadBoard(ParseError),
+    /// A line from the other end of a network game that made no sense.
+    BadMessage(ParseError),
+    BadFumen(ParseError),
+    BadPuzzle(ParseError),
+}
+
+impl Error {
+    /// Remembers which file the error came from.
+    pub fn in_file(self, path: &Path) -> Error {
+        Error::File(path.to_path_buf(), Box::new(self))
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::OutOfBounds => write!(f, "Tetromino out of bounds"),
+            Error::IllegalPlacement(p) => write!(f, "Illegal placement at ({}, {})", p.x, p.y),
+            Error::InvalidDimensions { width, height, hidden } => {
+                write!(f,
+                       "Invalid board of {}x{} with {} hidden rows",
+                       width,
+                       height,
+                       hidden)
+            }
+            Error::Io(_) => write!(f, "IO error"),
+            Error::File(ref path, _) => write!(f, "Problem with {}", path.display()),
+            Error::Config(_) => write!(f, "Invalid config"),
+            Error::BadRecords(_) => write!(f, "Corrupted records file"),
+            Error::UnsupportedRecords(v) => write!(f, "Unsupported records file version {}", v),
+            Error::BadReplay(_) => write!(f, "Corrupted replay"),
+            Error::UnsupportedReplay(v) => write!(f, "Unsupported replay version {}", v),
+            Error::BadWeights(_) => write!(f, "Invalid bot weights"),
+            Error::BadBoard(_) => write!(f, "Invalid board"),
+            Error::BadMessage(_) => write!(f, "Invalid network message"),
+            Error::BadFumen(_) => write!(f, "Invalid fumen"),
+            Error::BadPuzzle(_) => write!(f, "Invalid puzzle"),
         }
     }
 }
@@ -20,12 +73,101 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::OutOfBounds => "tetromino out of bounds",
+            Error::IllegalPlacement(_) => "illegal placement",
+            Error::InvalidDimensions { .. } => "invalid board dimensions",
+            Error::Io(ref e) => e.description(),
+            Error::File(_, ref e) => e.description(),
+            Error::Config(_) => "invalid config",
+            Error::BadRecords(_) => "corrupted records file",
+            Error::UnsupportedRecords(_) => "unsupported records file version",
+            Error::BadReplay(_) => "corrupted replay",
+            Error::UnsupportedReplay(_) => "unsupported replay version",
+            Error::BadWeights(_) => "invalid bot weights",
+            Error::BadBoard(_) => "invalid board",
+            Error::BadMessage(_) => "invalid network message",
+            Error::BadFumen(_) => "invalid fumen",
+            Error::BadPuzzle(_) => "invalid puzzle",
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
-            Error::OutOfBounds => None,
+            Error::Io(ref e) => Some(e),
+            Error::File(_, ref e) => Some(&**e),
+            Error::Config(ref e) |
+            Error::BadRecords(ref e) |
+            Error::BadReplay(ref e) |
+            Error::BadWeights(ref e) |
+            Error::BadBoard(ref e) |
+            Error::BadMessage(ref e) |
+            Error::BadFumen(ref e) |
+            Error::BadPuzzle(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Where in a text file parsing failed, and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Line and column, counted from 1.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        ParseError {
+            position: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn at<S: Into<String>>(line: usize, column: usize, message: S) -> Self {
+        ParseError {
+            position: Some((line, column)),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
         }
     }
 }
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The error followed by everything that caused it, for showing to players.
+pub fn report(e: &error::Error) -> String {
+    let mut text = e.to_string();
+    let mut cause = e.cause();
+    while let Some(e) = cause {
+        text.push_str(": ");
+        text.push_str(&e.to_string());
+        cause = e.cause();
+    }
+    text
+}
+
+#[test]
+fn report_follows_causes() {
+    let e = Error::Config(ParseError::at(3, 7, "expected a number"))
+        .in_file(Path::new("config.toml"));
+    assert_eq!("Problem with config.toml: Invalid config: line 3, column 7: expected a number",
+               report(&e));
+}
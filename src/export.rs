@@ -0,0 +1,226 @@
+use gif::{self, SetParameter};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::canvas::Canvas;
+use super::cli;
+use super::replay::{Replay, ReplayEvent};
+use super::Result;
+
+/// How long the last frame of a GIF stays up before it loops, in
+/// hundredths of a second.
+const FINAL_DELAY: u16 = 200;
+
+/// Which part of a replay becomes frames, and at what rate and size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Export {
+    pub fps: f64,
+    pub scale: f64,
+    /// Frames start once this many pieces are locked.
+    pub from: usize,
+    /// Frames stop once this many pieces are locked.
+    pub to: Option<usize>,
+}
+
+impl Default for Export {
+    fn default() -> Export {
+        Export {
+            fps: 20.0,
+            scale: 0.5,
+            from: 0,
+            to: None,
+        }
+    }
+}
+
+impl Export {
+    /// Re-simulates the replay, handing `each` a frame every 1 / fps of
+    /// game time within the chosen pieces. Returns the number of frames.
+    pub fn frames<F>(&self, replay: &Replay, mut each: F) -> Result<usize>
+        where F: FnMut(&Canvas) -> Result<()>
+    {
+        let mut game = replay.new_game();
+        let step = 1f64 / self.fps;
+        let mut clock = 0f64;
+        let mut next_frame = 0f64;
+        let mut count = 0;
+        for event in &replay.events {
+            Replay::apply(&mut game, event);
+            if let ReplayEvent::Update(dt) = *event {
+                clock += dt;
+            }
+            let pieces = game.statistics().pieces();
+            if self.to.map_or(false, |to| pieces > to) {
+                break;
+            }
+            while next_frame <= clock {
+                next_frame += step;
+                if pieces >= self.from {
+                    try!(each(&Canvas::of_game(&game, self.scale)));
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes an animated GIF that loops forever.
+    pub fn write_gif<W: Write>(&self, replay: &Replay, w: W) -> Result<usize> {
+        let size = replay.new_game().window_size();
+        let scaled = |length: u32| ((length as f64 * self.scale).round() as u32).max(1) as u16;
+        let mut encoder = try!(gif::Encoder::new(w, scaled(size[0]), scaled(size[1]), &[]));
+        try!(encoder.set(gif::Repeat::Infinite));
+
+        let delay = (100f64 / self.fps).round().max(1f64) as u16;
+        // Frames are written one behind so the last one can linger
+        let mut previous: Option<gif::Frame> = None;
+        let count = try!(self.frames(replay, |canvas| {
+            if let Some(frame) = previous.take() {
+                try!(encoder.write_frame(&frame));
+            }
+            let mut frame = gif_frame(canvas);
+            frame.delay = delay;
+            previous = Some(frame);
+            Ok(())
+        }));
+        if let Some(mut frame) = previous {
+            frame.delay = FINAL_DELAY;
+            try!(encoder.write_frame(&frame));
+        }
+        Ok(count)
+    }
+
+    /// Saves every frame as a numbered PNG in `dir`.
+    pub fn save_pngs(&self, replay: &Replay, dir: &Path) -> Result<usize> {
+        try!(fs::create_dir_all(dir));
+        let mut number = 0;
+        self.frames(replay, |canvas| {
+            number += 1;
+            canvas.save(&dir.join(format!("frame-{:05}.png", number)))
+        })
+    }
+}
+
+/// The canvas as a GIF frame. Game frames have only a handful of
+/// colors, so quantizing is only needed when there are too many.
+fn gif_frame(canvas: &Canvas) -> gif::Frame<'static> {
+    let size = canvas.size();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(canvas.pixels().len() / 4);
+    let mut seen: HashMap<(u8, u8, u8), u8> = HashMap::new();
+    for pixel in canvas.pixels().chunks(4) {
+        let color = (pixel[0], pixel[1], pixel[2]);
+        let found = seen.get(&color).cloned();
+        let index = match found {
+            Some(index) => index,
+            None if seen.len() < 256 => {
+                let index = seen.len() as u8;
+                seen.insert(color, index);
+                palette.extend_from_slice(&[color.0, color.1, color.2]);
+                index
+            }
+            None => {
+                let mut pixels = canvas.pixels().to_vec();
+                return gif::Frame::from_rgba(size[0] as u16, size[1] as u16, &mut pixels);
+            }
+        };
+        indices.push(index);
+    }
+    let mut frame = gif::Frame::default();
+    frame.width = size[0] as u16;
+    frame.height = size[1] as u16;
+    frame.palette = Some(palette);
+    frame.buffer = Cow::Owned(indices);
+    frame
+}
+
+/// `testris export REPLAY (--gif FILE | --frames DIR) [--fps N] [--scale X]
+/// [--from PIECES] [--to PIECES]`
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let path = match args.first() {
+        Some(path) if !path.starts_with("--") => PathBuf::from(path),
+        _ => {
+            println!("usage: testris export REPLAY (--gif FILE | --frames DIR) [--fps N] \
+                      [--scale X] [--from PIECES] [--to PIECES]");
+            return Ok(());
+        }
+    };
+    let replay = try!(Replay::load(&path));
+    let defaults = Export::default();
+    let export = Export {
+        fps: try!(cli::number_flag(args, "--fps", defaults.fps)).max(1f64),
+        scale: try!(cli::number_flag(args, "--scale", defaults.scale)),
+        from: try!(cli::number_flag(args, "--from", defaults.from)),
+        to: match cli::flag_value(args, "--to") {
+            Some(_) => Some(try!(cli::number_flag(args, "--to", 0usize))),
+            None => None,
+        },
+    };
+
+    if let Some(dir) = cli::flag_value(args, "--frames") {
+        let count = try!(export.save_pngs(&replay, Path::new(dir)));
+        println!("Saved {} frames to {}", count, dir);
+        return Ok(());
+    }
+    let output = match cli::flag_value(args, "--gif") {
+        Some(output) => PathBuf::from(output),
+        None => path.with_extension("gif"),
+    };
+    let file = try!(File::create(&output));
+    let count = try!(export.write_gif(&replay, BufWriter::new(file)));
+    println!("Saved {} frames to {}", count, output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bot::{self, Weights};
+    use game::Game;
+    use mode::Mode;
+    use rules::Ruleset;
+
+    fn recorded() -> Replay {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 8);
+        for _ in 0..4 {
+            bot::play_piece(&mut game, &Weights::default(), 0);
+            game.on_update(0.25);
+        }
+        game.replay().clone()
+    }
+
+    #[test]
+    fn frames_follow_game_time() {
+        let replay = recorded();
+        let export = Export {
+            fps: 10.0,
+            scale: 0.25,
+            ..Export::default()
+        };
+        let mut sizes = Vec::new();
+        let count = export.frames(&replay, |canvas| {
+                sizes.push(canvas.size());
+                Ok(())
+            })
+            .unwrap();
+        // A second of updates, plus the frame at the very start
+        assert_eq!(11, count);
+        assert!(sizes.iter().all(|&size| size == [195, 145]));
+
+        let later = Export { from: 2, ..export };
+        assert!(later.frames(&replay, |_| Ok(())).unwrap() < count);
+    }
+
+    #[test]
+    fn gifs_are_written() {
+        let mut gif = Vec::new();
+        let count = Export::default().write_gif(&recorded(), &mut gif).unwrap();
+        assert!(count > 0);
+        assert_eq!(&b"GIF89a"[..], &gif[..6]);
+        assert_eq!(0x3b, *gif.last().unwrap());
+    }
+}
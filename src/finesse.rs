@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+
+use super::board::GameBoard;
+use super::input::Command;
+use super::tetronimo::{SlideDirection, Tetromino};
+use super::transform::RotationDirection;
+
+/// How a placement compares to the fewest keys that could have made it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finesse {
+    pub optimal: Vec<Command>,
+    pub pressed: Vec<Command>,
+}
+
+impl Finesse {
+    pub fn is_fault(&self) -> bool {
+        self.pressed.len() > self.optimal.len()
+    }
+}
+
+/// Compares the keys pressed to move `placed` from `spawn` against the
+/// shortest sequence that reaches the same cells when hard dropped onto
+/// an empty board. Holding a slide key until the wall counts as one press.
+pub fn check(board: &GameBoard,
+             spawn: &Tetromino,
+             placed: &Tetromino,
+             pressed: Vec<Command>)
+             -> Option<Finesse> {
+    minimal_inputs(&board.empty_copy(), spawn, placed).map(|optimal| {
+        Finesse {
+            optimal: optimal,
+            pressed: pressed,
+        }
+    })
+}
+
+/// Breadth first search over one key presses, starting at `spawn`.
+pub fn minimal_inputs(empty: &GameBoard,
+                      spawn: &Tetromino,
+                      target: &Tetromino)
+                      -> Option<Vec<Command>> {
+    let goal = footprint(empty, target);
+
+    // Every state reached, along with the state and key it came from
+    let mut states: Vec<(Tetromino, Option<(usize, Command)>)> = vec![(spawn.clone(), None)];
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+
+    while let Some(current) = queue.pop_front() {
+        if footprint(empty, &states[current].0) == goal {
+            let mut path = Vec::new();
+            let mut index = current;
+            while let Some((parent, command)) = states[index].1 {
+                path.push(command);
+                index = parent;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for (command, piece) in one_press(empty, &states[current].0) {
+            let seen = states.iter().any(|&(ref p, _)| {
+                p.origin() == piece.origin() && p.orientation() == piece.orientation()
+            });
+            if !seen {
+                states.push((piece, Some((current, command))));
+                queue.push_back(states.len() - 1);
+            }
+        }
+    }
+    None
+}
+
+fn one_press(board: &GameBoard, piece: &Tetromino) -> Vec<(Command, Tetromino)> {
+    let mut result = Vec::new();
+    for &(command, direction) in &[(Command::SlideLeft, SlideDirection::Left),
+                                   (Command::SlideRight, SlideDirection::Right)] {
+        if let Some(tapped) = board.slide_piece(piece, direction) {
+            let mut held = tapped.clone();
+            while let Some(next) = board.slide_piece(&held, direction) {
+                held = next;
+            }
+            result.push((command, tapped));
+            result.push((command, held));
+        }
+    }
+    for &(command, direction) in &[(Command::RotateClockwise, RotationDirection::Clockwise),
+                                   (Command::RotateCounterClockwise,
+                                    RotationDirection::CounterClockwise)] {
+        if let Some((rotated, _)) = board.rotate_piece(piece, direction) {
+            result.push((command, rotated));
+        }
+    }
+    result
+}
+
+/// The cells a piece covers after being dropped, in a comparable order.
+fn footprint(board: &GameBoard, piece: &Tetromino) -> Vec<(i32, i32)> {
+    let mut cells: Vec<(i32, i32)> = board.drop_piece(piece)
+        .cells()
+        .iter()
+        .map(|p| (p.x, p.y))
+        .collect();
+    cells.sort();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::{GameBoard, Pixel};
+    use input::Command;
+    use tetronimo::{Shape, SlideDirection, Tetromino};
+    use transform::{Point, RotationDirection};
+
+    fn board() -> GameBoard {
+        GameBoard::new(10, 22, 2, Pixel::new(0f64, 0f64))
+    }
+
+    fn spawn(shape: Shape) -> Tetromino {
+        let mut piece = Tetromino::new_shape(shape);
+        piece.put(Point::new(5, 20));
+        piece
+    }
+
+    #[test]
+    fn nothing_to_do() {
+        let piece = spawn(Shape::T);
+        assert_eq!(Some(vec![]), minimal_inputs(&board(), &piece, &piece));
+    }
+
+    #[test]
+    fn hold_to_the_wall() {
+        let mut target = spawn(Shape::T);
+        target.put(Point::new(1, 0));
+        assert_eq!(Some(vec![Command::SlideLeft]),
+                   minimal_inputs(&board(), &spawn(Shape::T), &target));
+    }
+
+    #[test]
+    fn wall_then_tap_back() {
+        let mut target = spawn(Shape::O);
+        target.put(Point::new(2, 1));
+        let path = minimal_inputs(&board(), &spawn(Shape::O), &target);
+        assert_eq!(Some(vec![Command::SlideLeft, Command::SlideRight]), path);
+    }
+
+    #[test]
+    fn rotation_direction() {
+        let mut target = spawn(Shape::J);
+        target.rotate(&RotationDirection::CounterClockwise);
+        let path = minimal_inputs(&board(), &spawn(Shape::J), &target);
+        assert_eq!(Some(vec![Command::RotateCounterClockwise]), path);
+    }
+
+    #[test]
+    fn fault_when_too_many_presses() {
+        let mut target = spawn(Shape::T);
+        target.slide(SlideDirection::Right);
+        let finesse = check(&board(),
+                            &spawn(Shape::T),
+                            &target,
+                            vec![Command::SlideRight, Command::SlideRight, Command::SlideLeft])
+            .unwrap();
+        assert!(finesse.is_fault());
+        assert_eq!(vec![Command::SlideRight], finesse.optimal);
+    }
+}
@@ -0,0 +1,783 @@
+use std::ascii::AsciiExt;
+use std::fmt;
+use std::path::PathBuf;
+
+use super::block::Block;
+use super::board::{Dimensions, GameBoard, Pixel};
+use super::cli;
+use super::error::{Error, ParseError};
+use super::game::Game;
+use super::replay::Replay;
+use super::tetriscolor::Color;
+use super::tetronimo::{Shape, Tetromino, TetronimoState};
+use super::transform::{Point, RotationDirection};
+use super::Result;
+
+/// Fumen (https://fumen.zui.jp) is how board diagrams get shared, only
+/// its current version is understood.
+const PREFIX: &'static str = "v115@";
+const TABLE: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Characters a comment is written with once escaped, in code order.
+const COMMENT_TABLE: &'static [u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                                       [\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const MAX_COMMENT: usize = 4095;
+const WIDTH: usize = 10;
+/// Rows of the field, a garbage row waits below them.
+const TOP: usize = 23;
+const CELLS: usize = (TOP + 1) * WIDTH;
+const GRAY: u8 = 8;
+/// Comments that give the pieces of a puzzle start with this.
+const QUIZ: &'static str = "#Q=";
+
+/// One page of a diagram: a field with a piece on it.
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// The field before the piece is played.
+    pub board: GameBoard,
+    pub piece: Option<Tetromino>,
+    /// Whether the piece locks, clearing lines, before the next page.
+    pub lock: bool,
+    pub comment: String,
+}
+
+impl Page {
+    pub fn new(board: GameBoard) -> Page {
+        Page {
+            board: board,
+            piece: None,
+            lock: true,
+            comment: String::new(),
+        }
+    }
+
+    /// The position of a game, its queue going into the comment.
+    pub fn of_game(game: &Game) -> Page {
+        let active = game.active_piece();
+        let mut page = Page::new(game.board().clone());
+        if active.state == TetronimoState::Falling {
+            page.piece = Some(active.clone());
+        }
+        page.comment = Queue::of_game(game).to_string();
+        page
+    }
+
+    /// The pieces the comment gives, if it is a puzzle.
+    pub fn queue(&self) -> Option<Queue> {
+        Queue::parse(&self.comment)
+    }
+}
+
+/// Pieces a page is to be solved with, written `#Q=[hold](current)next`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Queue {
+    pub hold: Option<Shape>,
+    pub current: Option<Shape>,
+    pub next: Vec<Shape>,
+}
+
+impl Queue {
+    pub fn of_game(game: &Game) -> Queue {
+        Queue {
+            hold: game.held(),
+            current: Some(game.active_piece().shape()),
+            next: (0..5).filter_map(|i| game.preview(i)).map(|p| p.shape()).collect(),
+        }
+    }
+
+    pub fn parse(comment: &str) -> Option<Queue> {
+        if !comment.starts_with(QUIZ) {
+            return None;
+        }
+        let rest = &comment[QUIZ.len()..];
+        let (hold, rest) = match bracketed(rest, '[', ']') {
+            Some(found) => found,
+            None => return None,
+        };
+        let (current, rest) = match bracketed(rest, '(', ')') {
+            Some(found) => found,
+            None => return None,
+        };
+        let next = rest.chars().take_while(|c| c.is_alphabetic()).filter_map(Shape::by_letter);
+        Some(Queue {
+            hold: hold,
+            current: current,
+            next: next.collect(),
+        })
+    }
+
+    /// What is left after `shape` is played, which may take a hold.
+    fn play(&self, shape: Shape) -> Option<Queue> {
+        let mut next = self.next.clone();
+        let mut pop = || if next.is_empty() { None } else { Some(next.remove(0)) };
+        let (hold, current) = if self.current == Some(shape) {
+            (self.hold, pop())
+        } else if self.hold == Some(shape) {
+            (self.current, pop())
+        } else if self.hold.is_none() && self.next.first() == Some(&shape) {
+            pop();
+            (self.current, pop())
+        } else {
+            return None;
+        };
+        Some(Queue {
+            hold: hold,
+            current: current,
+            next: next,
+        })
+    }
+}
+
+/// A piece letter or nothing between two brackets, and what follows.
+fn bracketed(text: &str, open: char, close: char) -> Option<(Option<Shape>, &str)> {
+    let mut chars = text.char_indices();
+    if chars.next().map(|(_, c)| c) != Some(open) {
+        return None;
+    }
+    match (chars.next(), chars.next()) {
+        (Some((i, c)), _) if c == close => Some((None, &text[i + 1..])),
+        (Some((_, letter)), Some((i, c))) if c == close => {
+            Shape::by_letter(letter).map(|shape| (Some(shape), &text[i + 1..]))
+        }
+        _ => None,
+    }
+}
+
+impl fmt::Display for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = |shape: Option<Shape>| shape.map_or(String::new(), |s| s.letter().to_string());
+        let next: String = self.next.iter().map(|s| s.letter()).collect();
+        write!(f, "{}[{}]({}){}", QUIZ, letter(self.hold), letter(self.current), next)
+    }
+}
+
+fn bad<S: Into<String>>(message: S) -> Error {
+    Error::BadFumen(ParseError::new(message))
+}
+
+fn piece_code(shape: Shape) -> u8 {
+    match shape {
+        Shape::I => 1,
+        Shape::L => 2,
+        Shape::O => 3,
+        Shape::Z => 4,
+        Shape::T => 5,
+        Shape::J => 6,
+        Shape::S => 7,
+    }
+}
+
+fn shape_of(code: u32) -> Option<Shape> {
+    [Shape::I, Shape::L, Shape::O, Shape::Z, Shape::T, Shape::J, Shape::S]
+        .iter()
+        .find(|&&shape| piece_code(shape) as u32 == code)
+        .cloned()
+}
+
+/// Fumen colors cells by piece, ours only keeps the color, so anything
+/// that is not a piece color counts as garbage.
+fn cell_code(color: Option<Color>) -> u8 {
+    match color {
+        Some(color) => Shape::by_color(&color).map_or(GRAY, piece_code),
+        None => 0,
+    }
+}
+
+fn cell_color(code: u8) -> Option<Color> {
+    match code {
+        0 => None,
+        GRAY => Some(Color::gray()),
+        code => shape_of(code as u32).map(|shape| shape.color()),
+    }
+}
+
+/// Index of a cell, the top row first and the garbage row, y = -1, last.
+fn index(x: usize, y: i32) -> usize {
+    (TOP as i32 - 1 - y) as usize * WIDTH + x
+}
+
+fn field_of(board: &GameBoard) -> Result<Vec<u8>> {
+    if board.width() != WIDTH {
+        return Err(bad(format!("fields are {} wide, not {}", WIDTH, board.width())));
+    }
+    let mut field = vec![0; CELLS];
+    let rows = board.dimensions().height + board.dimensions().hidden;
+    for y in 0..rows {
+        for x in 0..WIDTH {
+            let code = cell_code(board.cell(&Point::new(x as i32, y as i32)));
+            if code != 0 && y >= TOP {
+                return Err(bad(format!("blocks above row {}", TOP)));
+            }
+            if code != 0 {
+                field[index(x, y as i32)] = code;
+            }
+        }
+    }
+    Ok(field)
+}
+
+/// A standard board, the garbage row is left out.
+fn board_of(field: &[u8]) -> Result<GameBoard> {
+    let dimensions = Dimensions::standard();
+    let rows = dimensions.height + dimensions.hidden;
+    let mut blocks = Vec::new();
+    for y in 0..TOP {
+        for x in 0..WIDTH {
+            if let Some(color) = cell_color(field[index(x, y as i32)]) {
+                if y >= rows {
+                    return Err(bad(format!("blocks above row {}", rows)));
+                }
+                blocks.push(Block::new(color, Point::new(x as i32, y as i32)));
+            }
+        }
+    }
+    let mut board = try!(GameBoard::try_new(dimensions, Pixel::new(0f64, 0f64)));
+    board.add_blocks(&blocks);
+    Ok(board)
+}
+
+/// Rotation codes count from upside down: reverse, right, spawn, left.
+/// Both ways this is also the number of clockwise turns from spawn.
+fn turns(code: u32) -> u32 {
+    (6 - code) % 4
+}
+
+/// Cells of a piece around its rotation center, the way fumen has them.
+fn fumen_cells(shape: Shape, rotation: u32, center: Point) -> [Point; 4] {
+    let spawn = match shape {
+        Shape::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        Shape::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        Shape::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        Shape::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        Shape::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        Shape::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        Shape::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    let mut cells = [center; 4];
+    for (cell, &(x, y)) in cells.iter_mut().zip(spawn.iter()) {
+        let (mut x, mut y) = (x, y);
+        for _ in 0..turns(rotation) {
+            let turned = (y, -x);
+            x = turned.0;
+            y = turned.1;
+        }
+        cell.x += x;
+        cell.y += y;
+    }
+    cells
+}
+
+fn corner(cells: &[Point; 4]) -> Point {
+    Point::new(cells.iter().map(|p| p.x).min().unwrap(),
+               cells.iter().map(|p| p.y).min().unwrap())
+}
+
+fn tetromino(shape: Shape, rotation: u32, center: Point) -> Tetromino {
+    let mut piece = Tetromino::new_shape(shape);
+    piece.state = TetronimoState::Falling;
+    for _ in 0..turns(rotation) {
+        piece.rotate(&RotationDirection::Clockwise);
+    }
+    let (target, current) = (corner(&fumen_cells(shape, rotation, center)), corner(&piece.cells()));
+    piece.translate(&Point::new(target.x - current.x, target.y - current.y));
+    piece
+}
+
+/// Rotation code and center of one of our pieces.
+fn center_of(piece: &Tetromino) -> (u32, Point) {
+    let rotation = turns(piece.orientation() as u32);
+    let at_zero = corner(&fumen_cells(piece.shape(), rotation, Point::new(0, 0)));
+    let corner = corner(&piece.cells());
+    (rotation, Point::new(corner.x - at_zero.x, corner.y - at_zero.y))
+}
+
+/// Pieces that look the same turned half way round are stored at one
+/// center, this is how far the real center is from it.
+fn shift(shape: Shape, rotation: u32) -> (i32, i32) {
+    match (shape, rotation) {
+        (Shape::O, 3) => (1, -1),
+        (Shape::O, 0) | (Shape::I, 0) | (Shape::Z, 3) => (1, 0),
+        (Shape::O, 2) | (Shape::I, 3) | (Shape::S, 2) | (Shape::Z, 2) => (0, -1),
+        (Shape::S, 1) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+struct Action {
+    piece: Option<Tetromino>,
+    rise: bool,
+    mirror: bool,
+    colorize: bool,
+    comment: bool,
+    lock: bool,
+}
+
+impl Action {
+    fn decode(value: u32) -> Action {
+        let flag = |bit: u32| (value / (8 * 4 * CELLS as u32) >> bit) & 1 == 1;
+        let piece = shape_of(value % 8).map(|shape| {
+            let rotation = value / 8 % 4;
+            let position = value / 32 % CELLS as u32;
+            let (dx, dy) = shift(shape, rotation);
+            let x = (position % WIDTH as u32) as i32 + dx;
+            let y = TOP as i32 - (position / WIDTH as u32) as i32 - 1 + dy;
+            tetromino(shape, rotation, Point::new(x, y))
+        });
+        Action {
+            piece: piece,
+            rise: flag(0),
+            mirror: flag(1),
+            colorize: flag(2),
+            comment: flag(3),
+            lock: !flag(4),
+        }
+    }
+
+    fn encode(&self) -> Result<u32> {
+        let (code, rotation, position) = match self.piece {
+            Some(ref piece) => {
+                let (rotation, center) = center_of(piece);
+                let (dx, dy) = shift(piece.shape(), rotation);
+                let (x, y) = (center.x - dx, center.y - dy);
+                if x < 0 || x >= WIDTH as i32 || y < -1 || y >= TOP as i32 {
+                    return Err(bad("piece is off the field"));
+                }
+                (piece_code(piece.shape()) as u32, rotation, index(x as usize, y) as u32)
+            }
+            None => (0, 0, 0),
+        };
+        let flags = [self.rise, self.mirror, self.colorize, self.comment, !self.lock]
+            .iter()
+            .enumerate()
+            .fold(0, |flags, (bit, &set)| flags | (set as u32) << bit);
+        Ok(((flags * CELLS as u32 + position) * 4 + rotation) * 8 + code)
+    }
+}
+
+/// Places a locked piece, clears lines and carries out the page flags.
+fn play(field: &mut Vec<u8>, action: &Action) {
+    if let Some(ref piece) = action.piece {
+        let (rotation, center) = center_of(piece);
+        for cell in &fumen_cells(piece.shape(), rotation, center) {
+            if cell.x >= 0 && cell.x < WIDTH as i32 && cell.y >= -1 && cell.y < TOP as i32 {
+                field[index(cell.x as usize, cell.y)] = piece_code(piece.shape());
+            }
+        }
+    }
+    let mut rows: Vec<Vec<u8>> = (0..TOP)
+        .map(|y| (0..WIDTH).map(|x| field[index(x, y as i32)]).collect())
+        .filter(|row: &Vec<u8>| row.iter().any(|&cell| cell == 0))
+        .collect();
+    let garbage: Vec<u8> = (0..WIDTH).map(|x| field[index(x, -1)]).collect();
+    if action.rise {
+        rows.insert(0, garbage);
+    }
+    rows.resize(TOP, vec![0; WIDTH]);
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..WIDTH {
+            let cell = if action.mirror { row[WIDTH - 1 - x] } else { row[x] };
+            field[index(x, y as i32)] = cell;
+        }
+    }
+    if action.rise {
+        for x in 0..WIDTH {
+            field[index(x, -1)] = 0;
+        }
+    }
+}
+
+/// JavaScript's `escape`, which fumen runs comments through.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        let c = unit as u8 as char;
+        if unit < 128 && (c.is_alphanumeric() || "@*_+-./".contains(c)) {
+            escaped.push(c);
+        } else if unit < 256 {
+            escaped.push_str(&format!("%{:02X}", unit));
+        } else {
+            escaped.push_str(&format!("%u{:04X}", unit));
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let hex = |from: usize, digits: usize| if from + digits <= chars.len() {
+        let digits: String = chars[from..from + digits].iter().cloned().collect();
+        u16::from_str_radix(&digits, 16).ok()
+    } else {
+        None
+    };
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (unit, length) = match (chars[i], chars.get(i + 1)) {
+            ('%', Some(&'u')) => (hex(i + 2, 4), 6),
+            ('%', _) => (hex(i + 1, 2), 3),
+            _ => (None, 1),
+        };
+        match unit {
+            Some(unit) => {
+                units.push(unit);
+                i += length;
+            }
+            None => {
+                units.extend(chars[i].to_string().encode_utf16());
+                i += 1;
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Reads numbers written as little endian base 64 digits.
+struct Reader {
+    digits: Vec<u32>,
+    next: usize,
+}
+
+impl Reader {
+    fn new(data: &str) -> Result<Reader> {
+        let digits = data.bytes()
+            .filter(|&b| b != b'?' && !(b as char).is_whitespace())
+            .map(|b| TABLE.iter().position(|&t| t == b).map(|d| d as u32))
+            .collect::<Option<Vec<u32>>>();
+        match digits {
+            Some(digits) => {
+                Ok(Reader {
+                    digits: digits,
+                    next: 0,
+                })
+            }
+            None => Err(bad("unexpected character")),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next >= self.digits.len()
+    }
+
+    fn poll(&mut self, count: usize) -> Result<u32> {
+        if self.next + count > self.digits.len() {
+            return Err(bad("data ends early"));
+        }
+        let value = self.digits[self.next..self.next + count]
+            .iter()
+            .rev()
+            .fold(0, |value, &digit| value * 64 + digit);
+        self.next += count;
+        Ok(value)
+    }
+}
+
+fn push(digits: &mut Vec<u32>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        digits.push(value % 64);
+        value /= 64;
+    }
+}
+
+/// Changes to the field as runs of the same difference. Returns false if
+/// nothing changed.
+fn read_field(reader: &mut Reader, field: &mut Vec<u8>) -> Result<bool> {
+    let mut cell = 0;
+    let mut changed = true;
+    while cell < CELLS {
+        let value = try!(reader.poll(2));
+        let (diff, run) = ((value / CELLS as u32) as i32, (value % CELLS as u32) as usize + 1);
+        if diff == 8 && run == CELLS {
+            changed = false;
+        }
+        if cell + run > CELLS {
+            return Err(bad("field has too many cells"));
+        }
+        for code in &mut field[cell..cell + run] {
+            let updated = *code as i32 + diff - 8;
+            if updated < 0 || updated > GRAY as i32 {
+                return Err(bad("field has an unknown block"));
+            }
+            *code = updated as u8;
+        }
+        cell += run;
+    }
+    Ok(changed)
+}
+
+fn write_field(digits: &mut Vec<u32>, previous: &[u8], field: &[u8]) {
+    let diffs: Vec<u32> = previous.iter()
+        .zip(field)
+        .map(|(&a, &b)| b as u32 + 8 - a as u32)
+        .collect();
+    let mut start = 0;
+    for i in 1..CELLS + 1 {
+        if i == CELLS || diffs[i] != diffs[start] {
+            push(digits, diffs[start] * CELLS as u32 + (i - start - 1) as u32, 2);
+            start = i;
+        }
+    }
+}
+
+/// The comment a page gets when it does not set one.
+fn carried_comment(comment: &str, action: &Action) -> String {
+    let played = match (action.lock, &action.piece, Queue::parse(comment)) {
+        (true, &Some(ref piece), Some(queue)) => queue.play(piece.shape()),
+        _ => None,
+    };
+    played.map_or(comment.to_string(), |queue| queue.to_string())
+}
+
+/// Reads a diagram, with or without the address of the site before it.
+pub fn decode(text: &str) -> Result<Vec<Page>> {
+    let data = match text.find("115@") {
+        Some(at) => &text[at + 4..],
+        None => return Err(bad(format!("expected a {} diagram", PREFIX))),
+    };
+    let mut reader = try!(Reader::new(data));
+    let mut pages = Vec::new();
+    let mut field = vec![0; CELLS];
+    let mut repeat = 0;
+    let mut comment = String::new();
+    while !reader.is_empty() {
+        if repeat > 0 {
+            repeat -= 1;
+        } else if !try!(read_field(&mut reader, &mut field)) {
+            repeat = try!(reader.poll(1));
+        }
+        let action = Action::decode(try!(reader.poll(3)));
+        if action.comment {
+            let length = try!(reader.poll(2)) as usize;
+            let mut escaped = String::new();
+            while escaped.len() < length {
+                let mut value = try!(reader.poll(5));
+                for _ in 0..(length - escaped.len()).min(4) {
+                    escaped.push(*try!(COMMENT_TABLE.get(value as usize % 96)
+                        .ok_or_else(|| bad("comment has an unknown character"))) as char);
+                    value /= 96;
+                }
+            }
+            comment = unescape(&escaped);
+        }
+        pages.push(Page {
+            board: try!(board_of(&field)),
+            piece: action.piece.clone(),
+            lock: action.lock,
+            comment: comment.clone(),
+        });
+        comment = carried_comment(&comment, &action);
+        if action.lock {
+            play(&mut field, &action);
+        }
+    }
+    if pages.is_empty() {
+        return Err(bad("no pages"));
+    }
+    Ok(pages)
+}
+
+/// Writes pages as a diagram, colored the standard way.
+pub fn encode(pages: &[Page]) -> Result<String> {
+    let mut digits = Vec::new();
+    let mut previous = vec![0; CELLS];
+    let mut repeat: Option<usize> = None;
+    let mut comment = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let mut field = try!(field_of(&page.board));
+        if field == previous {
+            match repeat {
+                Some(at) if digits[at] < 63 => digits[at] += 1,
+                _ => {
+                    write_field(&mut digits, &previous, &field);
+                    digits.push(0);
+                    repeat = Some(digits.len() - 1);
+                }
+            }
+        } else {
+            write_field(&mut digits, &previous, &field);
+            repeat = None;
+        }
+
+        let action = Action {
+            piece: page.piece.clone(),
+            rise: false,
+            mirror: false,
+            colorize: i == 0,
+            comment: page.comment != comment,
+            lock: page.lock,
+        };
+        push(&mut digits, try!(action.encode()), 3);
+        if action.comment {
+            let mut escaped = escape(&page.comment);
+            escaped.truncate(MAX_COMMENT);
+            push(&mut digits, escaped.len() as u32, 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let value = chunk.iter()
+                    .rev()
+                    .map(|&c| COMMENT_TABLE.iter().position(|&t| t == c).unwrap_or(0) as u32)
+                    .fold(0, |value, code| value * 96 + code);
+                push(&mut digits, value, 5);
+            }
+        }
+
+        comment = carried_comment(&page.comment, &action);
+        if action.lock {
+            play(&mut field, &action);
+        }
+        previous = field;
+    }
+
+    let data: Vec<char> = digits.iter().map(|&d| TABLE[d as usize] as char).collect();
+    let mut text = PREFIX.to_string();
+    // Long diagrams are broken up with a `?` after 42 characters and
+    // every 47 after that
+    for (i, c) in data.iter().enumerate() {
+        if i >= 42 && (i - 42) % 47 == 0 {
+            text.push('?');
+        }
+        text.push(*c);
+    }
+    Ok(text)
+}
+
+/// The field as rows of letters, top row first, `X` for garbage.
+pub fn draw(page: &Page) -> Vec<String> {
+    let board = &page.board;
+    let piece: Vec<Point> = page.piece.iter().flat_map(|p| p.cells().to_vec()).collect();
+    let letter = page.piece.as_ref().map_or('.', |p| p.shape().letter().to_ascii_lowercase());
+    let rows = board.dimensions().height + board.dimensions().hidden;
+    let top = (0..rows as i32)
+        .rev()
+        .find(|&y| (0..WIDTH as i32).any(|x| board.is_occupied(&Point::new(x, y))) ||
+                   piece.iter().any(|p| p.y == y))
+        .unwrap_or(0);
+    (0..top + 1)
+        .rev()
+        .map(|y| {
+            (0..board.width() as i32)
+                .map(|x| {
+                    let p = Point::new(x, y);
+                    match (piece.contains(&p), cell_code(board.cell(&p))) {
+                        (true, _) => letter,
+                        (false, 0) => '.',
+                        (false, GRAY) => 'X',
+                        (false, code) => shape_of(code as u32).unwrap().letter(),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `testris fumen DIAGRAM` shows the pages of a diagram and
+/// `testris fumen --replay FILE [--pieces N]` writes one.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    if let Some(path) = cli::flag_value(args, "--replay") {
+        let replay = try!(Replay::load(&PathBuf::from(path)));
+        let game = match cli::flag_value(args, "--pieces") {
+            Some(_) => replay.play_until(try!(cli::number_flag(args, "--pieces", 0usize))),
+            None => replay.play(),
+        };
+        println!("{}", try!(encode(&[Page::of_game(&game)])));
+        return Ok(());
+    }
+    let text = match args.first() {
+        Some(text) => text,
+        None => {
+            println!("usage: testris fumen DIAGRAM");
+            println!("       testris fumen --replay FILE [--pieces N]");
+            return Ok(());
+        }
+    };
+    for (i, page) in try!(decode(text)).iter().enumerate() {
+        println!("Page {}", i + 1);
+        for row in draw(page) {
+            println!("  {}", row);
+        }
+        if !page.comment.is_empty() {
+            println!("  {}", page.comment);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::{Dimensions, GameBoard, Pixel};
+    use tetronimo::{Shape, Tetromino, TetronimoState};
+    use transform::{Point, RotationDirection};
+
+    fn empty() -> GameBoard {
+        GameBoard::try_new(Dimensions::standard(), Pixel::new(0f64, 0f64)).unwrap()
+    }
+
+    #[test]
+    fn empty_and_single_piece_diagrams() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(1, pages.len());
+        assert!(pages[0].board.is_empty() && pages[0].piece.is_none());
+        assert_eq!("v115@vhAAgH", encode(&[Page::new(empty())]).unwrap());
+
+        // A T in spawn orientation centered on the fifth column
+        let pages = decode("https://fumen.zui.jp/?v115@vhAVQJ").unwrap();
+        let piece = pages[0].piece.clone().unwrap();
+        assert_eq!(Shape::T, piece.shape());
+        let mut cells = piece.cells().to_vec();
+        cells.sort_by_key(|p| (p.y, p.x));
+        assert_eq!(vec![Point::new(3, 0), Point::new(4, 0), Point::new(5, 0), Point::new(4, 1)],
+                   cells);
+        assert_eq!("v115@vhAVQJ", encode(&pages).unwrap());
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        // Each page plays the current piece of the quiz, which the
+        // pages after it need not repeat
+        let shapes = [Shape::I, Shape::O, Shape::S, Shape::Z];
+        let mut page = Page::new(empty());
+        page.board.add_garbage(2, 3);
+        let mut pages = Vec::new();
+        for (i, &shape) in shapes.iter().enumerate() {
+            let mut piece = Tetromino::new_shape(shape);
+            piece.state = TetronimoState::Falling;
+            piece.rotate(&RotationDirection::Clockwise);
+            piece.put(Point::new(2 + 2 * i as i32, 6));
+            let queue = Queue {
+                hold: None,
+                current: Some(shape),
+                next: shapes[i + 1..].to_vec(),
+            };
+            page.comment = queue.to_string();
+            page.piece = Some(piece.clone());
+            pages.push(page.clone());
+            page.board.place(&piece).unwrap();
+        }
+        page.piece = None;
+        page.comment = "100% ñ \u{1F600} (done)".to_string();
+        pages.push(page);
+
+        let text = encode(&pages).unwrap();
+        let decoded = decode(&text).unwrap();
+        assert_eq!(pages.len(), decoded.len());
+        for (page, copy) in pages.iter().zip(&decoded) {
+            assert_eq!(page.board.bits(), copy.board.bits());
+            assert_eq!(draw(page), draw(copy));
+            assert_eq!(page.comment, copy.comment);
+        }
+        assert_eq!("#Q=[](S)Z", decoded[2].comment);
+        assert!(text.contains('?'));
+        assert_eq!(text, encode(&decoded).unwrap());
+    }
+
+    #[test]
+    fn quiz_comments_give_the_queue() {
+        let queue = Queue::parse("#Q=[T](I)LOJ").unwrap();
+        assert_eq!(Some(Shape::T), queue.hold);
+        assert_eq!(Some(Shape::I), queue.current);
+        assert_eq!(vec![Shape::L, Shape::O, Shape::J], queue.next);
+        assert_eq!("#Q=[T](I)LOJ", queue.to_string());
+        assert_eq!("#Q=[I](L)OJ", queue.play(Shape::T).unwrap().to_string());
+        assert_eq!(None, Queue::parse("#Q=[](I"));
+        assert_eq!(None, Queue::parse("hello"));
+    }
+}
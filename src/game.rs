@@ -1,12 +1,43 @@
 use piston_window::*;
+use rand::{Rng, XorShiftRng};
+use std::collections::VecDeque;
 use super::block::Block;
-use super::board::{GameBoard, Pixel};
+use super::bot::{self, Weights};
+use super::board::{Dimensions, GameBoard, Pixel};
+use super::canvas::Quad;
 use super::tetronimo::{SlideDirection, Shape, Tetromino, TetrominoGenerator, TetronimoState};
 use super::transform::{RotationDirection, Point};
 use super::input;
 use super::limit;
-
-
+use super::finesse;
+use super::font;
+use super::mode::{Mode, Outcome};
+use super::puzzle::{Goal, Puzzle};
+use super::replay::{Replay, ReplayEvent};
+use super::rules::Ruleset;
+use super::score::{Award, Scorer};
+use super::seed::{self, Stream};
+use super::spin::{self, LineClear};
+use super::stats::Statistics;
+use super::tetriscolor::Color;
+use super::error::Error;
+use super::Result;
+
+
+/// How long clear callouts stay on screen, in seconds.
+const CALLOUT_TIME: f64 = 2.0;
+/// Size of a font pixel when drawing text.
+pub const TEXT_SCALE: f64 = 2.0;
+/// Size of a cell in the previews, and the largest one on the field.
+const PREVIEW_UNIT: f64 = 25.0;
+/// Room the playing field may take up on screen, in pixels.
+const FIELD_AREA: [f64; 2] = [1000.0, 500.0];
+const MARGIN: f64 = 20.0;
+/// Space between the field and the previews.
+const SIDE_GAP: f64 = 130.0;
+/// Previews plus the statistics panel.
+const SIDE_WIDTH: f64 = 380.0;
+const WINDOW_HEIGHT: f64 = 580.0;
 
 #[derive(Debug, Clone)]
 enum KeyAction {
@@ -15,9 +46,29 @@ enum KeyAction {
     Unknown,
 }
 
+/// Something that changed the game, for anyone following along.
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    Spawn(Tetromino),
+    /// The active piece went into the hold box.
+    Hold(Shape),
+    Lock(Tetromino),
+    /// Sent after the lock that cleared the lines.
+    Clear(Award),
+    /// Garbage lines that rose, with the column of their hole.
+    Garbage(usize, usize),
+    Over(Outcome),
+}
+
+struct Callout {
+    lines: Vec<String>,
+    remaining: f64,
+}
+
 pub struct Game {
     gameboard: GameBoard,
     upcoming: GameBoard,
+    hold: GameBoard,
     tetronimo_generator: TetrominoGenerator,
     unit_width: f64,
     slide_timer: limit::RateLimiter,
@@ -28,72 +79,361 @@ pub struct Game {
     ghost_piece: Tetromino,
     key_mapping: input::KeyMap,
     command_state: input::CommandState,
+    ruleset: Ruleset,
+    last_kick: Option<usize>,
+    scorer: Scorer,
+    callout: Option<Callout>,
+    held_piece: Option<Shape>,
+    can_hold: bool,
+    stats: Statistics,
+    mode: Mode,
+    outcome: Option<Outcome>,
+    replay: Replay,
+    leaderboard: Vec<String>,
+    show_leaderboard: bool,
+    show_hint: bool,
+    hint_weights: Weights,
+    /// Where the engine would put the active piece, while hints are on.
+    hint: Option<Tetromino>,
+    /// Garbage sent by an opponent and not yet added, one entry per attack.
+    incoming: VecDeque<usize>,
+    /// Attack earned since the last `take_attack`.
+    outgoing: usize,
+    /// Picks the holes in garbage lines.
+    garbage_rng: XorShiftRng,
+    /// What happened since the last `take_events`, kept only once
+    /// `enable_events` has been called.
+    events: Option<Vec<GameEvent>>,
+    /// What a puzzle asks for, puzzles end once it is met or out of reach.
+    goal: Option<Goal>,
 }
 
 
 impl Game {
-    pub fn new() -> Self {
-        let mut key_map = input::KeyMap::new();
-        key_map.insert(Key::Up, input::Command::RotateClockwise);
-        key_map.insert(Key::Down, input::Command::DownFast);
-        key_map.insert(Key::Left, input::Command::SlideLeft);
-        key_map.insert(Key::Right, input::Command::SlideRight);
-        key_map.insert(Key::Space, input::Command::Lock);
-        Game {
-            gameboard: GameBoard::new(10, 22, 2, Pixel::new(20f64, 500f64)),
-            upcoming: GameBoard::new(6, 9, 0, Pixel::new(400f64, 500f64)),
-            tetronimo_generator: TetrominoGenerator::new(),
-            unit_width: 25f64,
+    /// A game on the standard 10x20 field.
+    pub fn new(mode: Mode, ruleset: Ruleset, seed: u32) -> Self {
+        Game::with_dimensions(mode, ruleset, seed, Dimensions::standard()).unwrap()
+    }
+
+    pub fn with_dimensions(mode: Mode,
+                           ruleset: Ruleset,
+                           seed: u32,
+                           dimensions: Dimensions)
+                           -> Result<Self> {
+        let unit = cell_unit(dimensions);
+        let field = Pixel::new(MARGIN, dimensions.height as f64 * unit);
+        let gameboard = try!(GameBoard::try_new(dimensions, field));
+        let side_x = side_x(dimensions);
+
+        Ok(Game {
+            gameboard: gameboard,
+            upcoming: GameBoard::new(6, 9, 0, Pixel::new(side_x, 500f64)),
+            hold: GameBoard::new(6, 3, 0, Pixel::new(side_x, 260f64)),
+            tetronimo_generator: TetrominoGenerator::new(seed),
+            unit_width: unit,
             slide_timer: limit::RateLimiter::new(0.05f64, Some(0.17f64)),
             rotate_timer: limit::RateLimiter::new(0.4f64, Some(0.4f64)),
             gravity_timer: limit::RateLimiter::new(0.5f64, None),
             fast_fall_timer: limit::RateLimiter::new(0.05f64, None),
-            key_mapping: key_map,
+            key_mapping: input::KeyMap::standard(),
             command_state: input::CommandState::new(),
             active_piece: Tetromino::new(),
             ghost_piece: Tetromino::new(),
+            ruleset: ruleset,
+            last_kick: None,
+            scorer: Scorer::new(),
+            callout: None,
+            held_piece: None,
+            can_hold: true,
+            stats: Statistics::new(),
+            mode: mode,
+            outcome: None,
+            replay: Replay::new(seed, mode, ruleset, dimensions),
+            leaderboard: Vec::new(),
+            show_leaderboard: false,
+            show_hint: false,
+            hint_weights: Weights::default(),
+            hint: None,
+            incoming: VecDeque::new(),
+            outgoing: 0,
+            garbage_rng: seed::seeded_rng(seed, Stream::Garbage),
+            events: None,
+            goal: None,
+        })
+    }
+
+    /// A game on the puzzle's board that deals only the puzzle's pieces.
+    /// Its replay can not be played back without the puzzle.
+    pub fn puzzle(puzzle: &Puzzle, ruleset: Ruleset) -> Result<Self> {
+        let board = &puzzle.setup.board;
+        let mut game = try!(Game::with_dimensions(Mode::Marathon, ruleset, 0, board.dimensions()));
+        let blocks: Vec<Block> =
+            board.blocks().into_iter().filter(|b| board.is_occupied(&b.point)).collect();
+        game.gameboard.add_blocks(&blocks);
+        game.tetronimo_generator = TetrominoGenerator::fixed(&puzzle.setup.queue);
+        game.held_piece = puzzle.setup.hold;
+        game.goal = Some(puzzle.goal);
+        game.announce(&puzzle.goal.describe());
+        Ok(game)
+    }
+
+    /// Window size that fits the field, previews and statistics.
+    pub fn window_size(&self) -> [u32; 2] {
+        Game::window_size_for(self.gameboard.dimensions())
+    }
+
+    /// Window size of a game on a field this big.
+    pub fn window_size_for(dimensions: Dimensions) -> [u32; 2] {
+        let width = side_x(dimensions) + SIDE_WIDTH;
+        [width.ceil() as u32, WINDOW_HEIGHT as u32]
+    }
+
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+        self.ruleset
+    }
+
+    pub fn score(&self) -> u64 {
+        self.scorer.score()
+    }
+
+    pub fn lines(&self) -> usize {
+        self.scorer.lines()
+    }
+
+    pub fn board(&self) -> &GameBoard {
+        &self.gameboard
+    }
+
+    pub fn active_piece(&self) -> &Tetromino {
+        &self.active_piece
+    }
+
+    /// The `i`th upcoming piece, not yet placed on the board. Puzzles
+    /// run out of pieces.
+    pub fn preview(&self, i: usize) -> Option<Tetromino> {
+        self.tetronimo_generator.peek(i)
+    }
+
+    /// The piece in the hold box, if any.
+    pub fn held(&self) -> Option<Shape> {
+        self.held_piece
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.stats
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Replaces the keys that control this game.
+    pub fn set_key_map(&mut self, key_map: input::KeyMap) {
+        self.key_mapping = key_map;
+    }
+
+    /// Queues garbage from an opponent. It cancels against the next
+    /// attacks, and whatever is left rises after a lock that clears
+    /// nothing.
+    pub fn receive_garbage(&mut self, lines: usize) {
+        if lines == 0 || self.is_over() {
+            return;
+        }
+        self.replay.record(ReplayEvent::Garbage(lines));
+        self.incoming.push_back(lines);
+    }
+
+    /// Garbage lines waiting to rise.
+    pub fn incoming_garbage(&self) -> usize {
+        self.incoming.iter().sum()
+    }
+
+    /// Lines to send to the opponent, counted since the last call.
+    pub fn take_attack(&mut self) -> usize {
+        let attack = self.outgoing;
+        self.outgoing = 0;
+        attack
+    }
+
+    /// Ends the game as the winner, when the opponent has topped out.
+    pub fn win(&mut self) {
+        if !self.is_over() {
+            info!("Won with {:?}", self.stats);
+            self.finish(Outcome::Won);
+        }
+    }
+
+    /// Starts keeping events for `take_events`, for a game someone is
+    /// following.
+    pub fn enable_events(&mut self) {
+        if self.events.is_none() {
+            self.events = Some(Vec::new());
         }
+    }
+
+    /// Everything that happened since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        self.events.as_mut().map_or(Vec::new(), |events| events.drain(..).collect())
+    }
+
+    fn record(&mut self, event: GameEvent) {
+        if let Some(ref mut events) = self.events {
+            events.push(event);
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) {
+        if !self.is_over() {
+            self.outcome = Some(outcome);
+            self.record(GameEvent::Over(outcome));
+        }
+    }
+
+    /// Flashes a line of text over the field, like a clear callout.
+    pub fn announce(&mut self, text: &str) {
+        self.callout = Some(Callout {
+            lines: vec![text.to_string()],
+            remaining: CALLOUT_TIME,
+        });
+    }
+
+    /// Lines of text shown on the records screen.
+    pub fn set_leaderboard(&mut self, lines: Vec<String>) {
+        self.leaderboard = lines;
+    }
 
+    /// Weights used to suggest placements when hints are shown.
+    pub fn set_hint_weights(&mut self, weights: Weights) {
+        self.hint_weights = weights;
+        self.update_hint();
     }
 
+    pub fn toggle_hint(&mut self) {
+        self.show_hint = !self.show_hint;
+        self.update_hint();
+    }
+
+    pub fn hint(&self) -> Option<&Tetromino> {
+        self.hint.as_ref()
+    }
 
-    fn new_piece(&mut self) -> Tetromino {
-        let mut new_piece = self.tetronimo_generator.pop();
+    /// Searches for the best placement of a new piece, with one preview.
+    fn update_hint(&mut self) {
+        let falling = self.active_piece.state == TetronimoState::Falling;
+        self.hint = if self.show_hint && falling && !self.is_over() {
+            bot::suggest(self, &self.hint_weights, 1).map(|placement| {
+                let mut hint = placement.piece;
+                hint.state = TetronimoState::Hint;
+                hint
+            })
+        } else {
+            None
+        };
+    }
+
+    /// Locks the active piece at `piece` straight away, as if it had been
+    /// moved there with its last rotation using wall kick `kick`. Lets
+    /// bots play without waiting for timers.
+    pub fn place(&mut self, piece: &Tetromino, kick: Option<usize>) -> Result<()> {
+        let falling = self.active_piece.state == TetronimoState::Falling;
+        if self.is_over() || !falling || piece.shape() != self.active_piece.shape() ||
+           !self.gameboard.check_piece(piece) {
+            return Err(Error::IllegalPlacement(piece.origin()));
+        }
+        self.replay.record(ReplayEvent::Place(piece.origin(), piece.orientation(), kick));
+        self.active_piece = self.gameboard.drop_piece(piece);
+        self.active_piece.state = TetronimoState::Falling;
+        self.last_kick = if self.active_piece.origin() == piece.origin() {
+            kick
+        } else {
+            None
+        };
+        // Nothing was pressed, so there is no finesse to judge
+        self.lock(false);
+        Ok(())
+    }
+
+    /// Centered on the lower of the two hidden rows. Pieces reach one row
+    /// above it, and the O piece one row below, into the top visible row.
+    pub fn spawn_point(&self) -> Point {
+        let d = self.gameboard.dimensions();
+        Point::new((d.width / 2) as i32, (d.height + d.hidden - 2) as i32)
+    }
+
+    fn spawn(&mut self, mut new_piece: Tetromino) {
         new_piece.state = TetronimoState::Falling;
-        new_piece.put(Point::new(5, 21));
-        new_piece
+        new_piece.put(self.spawn_point());
+        if !self.gameboard.check_piece(&new_piece) {
+            info!("Topped out with {:?}", self.stats);
+            self.finish(Outcome::ToppedOut);
+        }
+        self.record(GameEvent::Spawn(new_piece.clone()));
+        self.ghost_piece = self.ghost(&new_piece);
+        self.active_piece = new_piece;
+        self.update_hint();
     }
 
-    fn check_and_update(&mut self, direction: RotationDirection) {
-        let mut new_piece = self.active_piece.clone();
-        new_piece.rotate(&direction);
-        let translations = new_piece.wall_kick_options(&direction);
-        for test_translate in &translations {
-            let mut test_piece = new_piece.clone();
-            test_piece.translate(test_translate);
-            if self.gameboard.check_piece(&test_piece) {
-                self.active_piece = test_piece;
-                self.ghost_piece = self.ghost(&self.active_piece);
-                return;
+    fn hold(&mut self) {
+        if !self.can_hold {
+            return;
+        }
+        let next = match self.held_piece.take() {
+            Some(shape) => Tetromino::new_shape(shape),
+            None => {
+                match self.tetronimo_generator.pop() {
+                    Some(next) => next,
+                    // Nothing left in a puzzle to swap with
+                    None => return,
+                }
             }
+        };
+        self.held_piece = Some(self.active_piece.shape());
+        self.record(GameEvent::Hold(self.active_piece.shape()));
+        self.can_hold = false;
+        self.last_kick = None;
+        self.command_state.take_presses();
+        self.stats.on_hold();
+        self.clear_timers();
+        self.spawn(next);
+    }
+
+    fn check_and_update(&mut self, direction: RotationDirection) {
+        if let Some((piece, kick)) = self.gameboard.rotate_piece(&self.active_piece, direction) {
+            self.active_piece = piece;
+            self.ghost_piece = self.ghost(&self.active_piece);
+            self.last_kick = Some(kick);
         }
     }
 
     fn slide(&mut self) {
         let event = self.slide_timer.get_event();
         let maybe_direction = self.command_state.do_slide();
-        let mut test_piece = self.active_piece.clone();
-        match (event, maybe_direction) {
-            (Some(_), Some(input::SlideDirection::Left)) => test_piece.slide(SlideDirection::Left),
-            (Some(_), Some(input::SlideDirection::Right)) => {
-                test_piece.slide(SlideDirection::Right)
+        let direction = match (event, maybe_direction) {
+            (Some(_), Some(input::SlideDirection::Left)) => SlideDirection::Left,
+            (Some(_), Some(input::SlideDirection::Right)) => SlideDirection::Right,
+            (_, None) => {
+                self.slide_timer.reset();
+                return;
             }
-            (_, None) => self.slide_timer.reset(),
-            (None, _) => {}
-        }
-        if self.gameboard.check_piece(&test_piece) {
-            self.active_piece = test_piece;
+            (None, _) => return,
+        };
+        if let Some(piece) = self.gameboard.slide_piece(&self.active_piece, direction) {
+            self.active_piece = piece;
             self.ghost_piece = self.ghost(&self.active_piece);
+            self.last_kick = None;
         }
     }
 
@@ -128,8 +468,9 @@ impl Game {
                 test_piece.move_down();
                 if self.gameboard.check_piece(&test_piece) {
                     self.active_piece = test_piece;
+                    self.last_kick = None;
                 } else {
-                    self.lock();
+                    self.lock(true);
                 }
             }
             None => {} //Timer says wait
@@ -137,29 +478,114 @@ impl Game {
     }
 
 
-    fn ghost(&self, piece: &Tetromino) -> Tetromino {
-        let mut test = piece.clone();
-        let mut ghost = test.clone();
-
-        while self.gameboard.check_piece(&test) {
-            ghost = test.clone();
-            test.move_down();
-        }
-
+    /// Where `piece` would land, drawn faintly.
+    pub fn ghost(&self, piece: &Tetromino) -> Tetromino {
+        let mut ghost = self.gameboard.drop_piece(piece);
         ghost.state = TetronimoState::Ghost;
         ghost
     }
 
-    fn lock(&mut self) {
-        self.gameboard.add_blocks(&self.active_piece.blocks());
+    fn lock(&mut self, judge_finesse: bool) {
+        let spin = match self.last_kick {
+            Some(kick) => {
+                spin::detect(&self.gameboard,
+                             &self.active_piece,
+                             kick,
+                             self.ruleset.spin_detection)
+            }
+            None => None,
+        };
+        self.last_kick = None;
+
+        let mut spawned = Tetromino::new_shape(self.active_piece.shape());
+        spawned.put(self.spawn_point());
+        let pressed = self.command_state.take_presses();
+        let finesse_fault = judge_finesse && {
+            let finesse = finesse::check(&self.gameboard, &spawned, &self.active_piece, pressed);
+            debug!("Finesse {:?}", finesse);
+            finesse.map(|f| f.is_fault()).unwrap_or(false)
+        };
+
+        if let Err(e) = self.gameboard.place(&self.active_piece) {
+            error!("Could not lock {:?}: {}", self.active_piece, e);
+        }
+        self.record(GameEvent::Lock(self.active_piece.clone()));
         self.active_piece.state = TetronimoState::Nonexistant;
-        self.gameboard.wipe_full_rows();
+        self.hint = None;
+        let lines = self.gameboard.wipe_full_rows();
+        let clear = LineClear {
+            lines: lines,
+            spin: spin,
+        };
+        let perfect_clear = lines > 0 && self.gameboard.is_empty();
+        let award = self.scorer.award(clear, perfect_clear);
+        self.stats.on_lock(&award);
+        if lines > 0 {
+            self.record(GameEvent::Clear(award.clone()));
+        }
+        self.can_hold = true;
+        self.exchange_garbage(award.attack, lines > 0);
+        if let Some(goal) = self.mode.goal_lines() {
+            if self.scorer.lines() >= goal {
+                info!("Completed {} with {:?}", self.mode.name(), self.stats);
+                self.finish(Outcome::Completed);
+            }
+        }
+        if let Some(goal) = self.goal {
+            let pieces_left = self.tetronimo_generator.peek(0).is_some() ||
+                              self.held_piece.is_some();
+            if goal.is_met(&award, self.scorer.lines(), pieces_left) {
+                info!("Solved a puzzle with {:?}", self.stats);
+                self.finish(Outcome::Completed);
+            } else if !pieces_left {
+                info!("Ran out of pieces with {:?}", self.stats);
+                self.finish(Outcome::Failed);
+            }
+        }
+        let mut callouts = award.callouts();
+        if finesse_fault {
+            self.stats.on_finesse_fault();
+            callouts.push("Finesse Fault".to_string());
+        }
+        if !callouts.is_empty() {
+            info!("Scored {:?}", award);
+            self.callout = Some(Callout {
+                lines: callouts,
+                remaining: CALLOUT_TIME,
+            });
+        }
         self.command_state.clear_state();
         self.clear_timers();
     }
 
 
+    /// Attack cancels incoming garbage first and the rest is sent on.
+    /// Garbage still waiting rises when the lock cleared nothing, one
+    /// hole per attack.
+    fn exchange_garbage(&mut self, attack: usize, cleared: bool) {
+        self.outgoing += cancel_garbage(&mut self.incoming, attack);
+        if cleared {
+            return;
+        }
+        let width = self.gameboard.width();
+        while let Some(lines) = self.incoming.pop_front() {
+            let hole = self.garbage_rng.gen_range(0, width);
+            let fits = self.gameboard.add_garbage(lines, hole);
+            self.record(GameEvent::Garbage(lines, hole));
+            if !fits {
+                info!("Buried by garbage with {:?}", self.stats);
+                self.finish(Outcome::ToppedOut);
+            }
+        }
+    }
+
     pub fn on_input(&mut self, inp: &Input) {
+        if let Input::Press(Button::Keyboard(Key::Tab)) = *inp {
+            self.show_leaderboard = !self.show_leaderboard;
+        }
+        if let Input::Press(Button::Keyboard(Key::H)) = *inp {
+            self.toggle_hint();
+        }
         let (command, action) = match *inp {
             Input::Press(Button::Keyboard(button)) => {
                 (self.key_mapping.get(&button), KeyAction::Press)
@@ -171,49 +597,149 @@ impl Game {
         };
         debug!("{:?} {:?}", command, action);
         match (command, action) {
-            (Some(c), KeyAction::Press) => self.command_state.key_press(*c),
-            (Some(c), KeyAction::Release) => self.command_state.key_release(*c),
+            (Some(&c), KeyAction::Press) => self.on_press(c),
+            (Some(&c), KeyAction::Release) => self.on_release(c),
             (_, _) => {}
         }
     }
 
+    pub fn on_press(&mut self, command: input::Command) {
+        self.replay.record(ReplayEvent::Press(command));
+        self.stats.on_key();
+        self.command_state.key_press(command)
+    }
+
+    pub fn on_release(&mut self, command: input::Command) {
+        self.replay.record(ReplayEvent::Release(command));
+        self.command_state.key_release(command)
+    }
+
 
     pub fn on_render<G>(&self, g: &mut G, view: math::Matrix2d)
         where G: Graphics
     {
-        let Pixel { x, y } = self.gameboard.point;
+        for quad in self.quads() {
+            rectangle(quad.color, quad.area, view, g);
+        }
+    }
+
+    /// Everything on screen as rectangles in window coordinates, back to
+    /// front, shared by the window and the software renderer.
+    pub fn quads(&self) -> Vec<Quad> {
+        let mut quads = Vec::new();
+        let field = self.gameboard.point;
+        let x = field.x;
+        let unit = self.unit_width;
         let height = self.gameboard.height() as i32;
 
         for block in self.gameboard.blocks() {
-            self.render_block(g, view, x, y, block);
+            quads.push(block_quad(field, unit, block));
         }
 
         for block in self.active_piece.blocks() {
             if block.point.y < height {
-                self.render_block(g, view, x, y, block);
+                quads.push(block_quad(field, unit, block));
             }
         }
         for block in self.ghost_piece.blocks() {
             if block.point.y < height {
-                self.render_block(g, view, x, y, block);
+                quads.push(block_quad(field, unit, block));
+            }
+        }
+        if let Some(ref hint) = self.hint {
+            for block in hint.blocks() {
+                if block.point.y < height {
+                    quads.push(block_quad(field, unit, block));
+                }
             }
         }
 
 
-        let Pixel { x: upcoming_x, y: upcoming_y } = self.upcoming.point;
+        let incoming = self.incoming_garbage().min(self.gameboard.height()) as f64 * unit;
+        if incoming > 0f64 {
+            let meter_x = x + self.gameboard.width() as f64 * unit + unit / 4f64;
+            let bottom = field.y + unit;
+            quads.push(Quad {
+                color: [0.9f32, 0.1f32, 0.1f32, 1f32],
+                area: [meter_x, bottom - incoming, unit / 2f64, incoming],
+            });
+        }
+
+        let upcoming_x = self.upcoming.point.x;
         for block in self.upcoming.blocks() {
-            self.render_block(g, view, upcoming_x, upcoming_y, block);
+            quads.push(block_quad(self.upcoming.point, PREVIEW_UNIT, block));
+        }
+
+        for block in self.hold.blocks() {
+            quads.push(block_quad(self.hold.point, PREVIEW_UNIT, block));
+        }
+        if let Some(shape) = self.held_piece {
+            let mut held = Tetromino::new_shape(shape);
+            held.state = TetronimoState::Frozen;
+            held.translate(&Point::new(3, 1));
+            for block in held.blocks() {
+                quads.push(block_quad(self.hold.point, PREVIEW_UNIT, block));
+            }
         }
 
         for i in 0..3 {
             let p = Point::new(3, 7 - i * 3);
-            let mut upcoming_tetronimo = self.tetronimo_generator.peek(i as usize);
+            let mut upcoming_tetronimo = match self.tetronimo_generator.peek(i as usize) {
+                Some(piece) => piece,
+                None => break,
+            };
             upcoming_tetronimo.state = TetronimoState::Frozen;
             upcoming_tetronimo.translate(&p);
             for block in upcoming_tetronimo.blocks() {
-                self.render_block(g, view, upcoming_x, upcoming_y, block);
+                quads.push(block_quad(self.upcoming.point, PREVIEW_UNIT, block));
             }
         }
+
+        let line_height = (font::GLYPH_HEIGHT + 3) as f64 * TEXT_SCALE;
+        let score = self.scorer.score().to_string();
+        let lines = self.scorer.lines().to_string();
+        for (i, text) in ["Score", score.as_str(), "Lines", lines.as_str()].iter().enumerate() {
+            text_quads(&mut quads, upcoming_x, 40f64 + i as f64 * line_height, text);
+        }
+
+        let panel_x = upcoming_x + (self.upcoming.width() + 1) as f64 * PREVIEW_UNIT;
+        for (i, text) in self.stats.panel().iter().enumerate() {
+            text_quads(&mut quads, panel_x, 40f64 + i as f64 * line_height, text);
+        }
+
+        let center = x + self.gameboard.width() as f64 * self.unit_width / 2f64;
+        if self.show_leaderboard || self.is_over() {
+            let board_width = self.gameboard.width() as f64 * self.unit_width;
+            let backdrop_height = 40f64 + self.leaderboard.len() as f64 * line_height;
+            let backdrop = [x, 240f64, board_width, backdrop_height];
+            quads.push(Quad {
+                color: [0f32, 0f32, 0f32, 0.8f32],
+                area: backdrop,
+            });
+            let title = match self.outcome {
+                Some(Outcome::ToppedOut) => "Game Over",
+                Some(Outcome::Completed) if self.goal.is_some() => "Solved",
+                Some(Outcome::Completed) => "Complete",
+                Some(Outcome::Won) => "You Win",
+                Some(Outcome::Failed) => "Failed",
+                None => "Records",
+            };
+            let width = font::width(title) as f64 * TEXT_SCALE;
+            text_quads(&mut quads, center - width / 2f64, 250f64, title);
+            for (i, text) in self.leaderboard.iter().enumerate() {
+                text_quads(&mut quads, x + 10f64, 280f64 + i as f64 * line_height, text);
+            }
+        }
+        if let Some(ref callout) = self.callout {
+            for (i, text) in callout.lines.iter().enumerate() {
+                let width = font::width(text) as f64 * TEXT_SCALE;
+                text_quads(&mut quads,
+                           center - width / 2f64,
+                           150f64 + i as f64 * line_height,
+                           text);
+            }
+        }
+        quads
     }
 
     fn clear_timers(&mut self) {
@@ -223,6 +749,19 @@ impl Game {
         self.rotate_timer.reset();
     }
 
+    fn update_callout(&mut self, dt: f64) {
+        let expired = match self.callout {
+            Some(ref mut callout) => {
+                callout.remaining -= dt;
+                callout.remaining <= 0f64
+            }
+            None => false,
+        };
+        if expired {
+            self.callout = None;
+        }
+    }
+
     fn update_timers(&mut self, dt: f64) {
         self.gravity_timer.elapsed(dt);
         self.fast_fall_timer.elapsed(dt);
@@ -231,14 +770,25 @@ impl Game {
     }
 
     pub fn on_update(&mut self, dt: f64) {
+        self.replay.record(ReplayEvent::Update(dt));
+        self.update_callout(dt);
+        if self.is_over() {
+            return;
+        }
         self.update_timers(dt);
+        self.stats.elapsed(dt);
 
         match self.active_piece.state {
             TetronimoState::Falling => {
                 debug!("Falling");
                 if self.command_state.lock() {
+                    if self.ghost_piece.origin() != self.active_piece.origin() {
+                        self.last_kick = None;
+                    }
                     self.active_piece = self.ghost_piece.clone();
-                    self.lock();
+                    self.lock(true);
+                } else if self.command_state.do_hold() {
+                    self.hold();
                 } else {
                     self.gravity();
                     self.slide();
@@ -246,20 +796,85 @@ impl Game {
                 }
             }
             TetronimoState::Nonexistant => {
-                self.active_piece = self.new_piece();
-                self.ghost_piece = self.ghost(&self.active_piece);
+                // The last piece of a puzzle can still come out of hold
+                let next = self.tetronimo_generator
+                    .pop()
+                    .or_else(|| self.held_piece.take().map(Tetromino::new_shape));
+                match next {
+                    Some(next) => self.spawn(next),
+                    None => self.finish(Outcome::Failed),
+                }
             }
             ref state => unreachable!("Found Active Tetronimo in State: {:?}", state),
         }
     }
+}
 
+/// Cells shrink until the field fits on screen.
+fn cell_unit(dimensions: Dimensions) -> f64 {
+    PREVIEW_UNIT.min(FIELD_AREA[0] / dimensions.width as f64)
+        .min(FIELD_AREA[1] / dimensions.height as f64)
+        .floor()
+}
 
-    fn render_block<G>(&self, g: &mut G, view: math::Matrix2d, x: f64, y: f64, block: Block)
-        where G: Graphics
-    {
-        let draw_x = x + block.point.x as f64 * self.unit_width;
-        let draw_y = y - block.point.y as f64 * self.unit_width;
-        let square = rectangle::square(draw_x, draw_y, self.unit_width * 0.95);
-        rectangle(block.color.as_list(), square, view, g);
+/// Left edge of the previews and statistics beside the field.
+fn side_x(dimensions: Dimensions) -> f64 {
+    MARGIN + dimensions.width as f64 * cell_unit(dimensions) + SIDE_GAP
+}
+
+fn block_quad(origin: Pixel, unit: f64, block: Block) -> Quad {
+    let draw_x = origin.x + block.point.x as f64 * unit;
+    let draw_y = origin.y - block.point.y as f64 * unit;
+    Quad {
+        color: block.color.as_list(),
+        area: rectangle::square(draw_x, draw_y, unit * 0.95),
+    }
+}
+
+/// Adds a line of text with its top left corner at (x, y).
+pub fn text_quads(quads: &mut Vec<Quad>, x: f64, y: f64, text: &str) {
+    let color = Color::white().as_list();
+    for (col, row) in font::pixels(text) {
+        quads.push(Quad {
+            color: color,
+            area: rectangle::square(x + col as f64 * TEXT_SCALE,
+                                    y + row as f64 * TEXT_SCALE,
+                                    TEXT_SCALE),
+        });
+    }
+}
+
+/// Takes `attack` lines off the oldest incoming garbage, returning
+/// what is left of the attack.
+fn cancel_garbage(incoming: &mut VecDeque<usize>, mut attack: usize) -> usize {
+    while attack > 0 {
+        match incoming.pop_front() {
+            Some(lines) if lines > attack => {
+                incoming.push_front(lines - attack);
+                attack = 0;
+            }
+            Some(lines) => attack -= lines,
+            None => break,
+        }
+    }
+    attack
+}
+
+#[test]
+fn attacks_cancel_garbage() {
+    let mut incoming: VecDeque<usize> = vec![2, 3].into_iter().collect();
+    assert_eq!(0, cancel_garbage(&mut incoming, 3));
+    assert_eq!(vec![2], incoming.iter().cloned().collect::<Vec<_>>());
+    assert_eq!(2, cancel_garbage(&mut incoming, 4));
+    assert!(incoming.is_empty());
+    assert_eq!(1, cancel_garbage(&mut incoming, 1));
+}
+
+#[test]
+fn pieces_spawn_inside_the_board() {
+    for &shape in &[Shape::I, Shape::O, Shape::T, Shape::L, Shape::J, Shape::S, Shape::Z] {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 0);
+        game.spawn(Tetromino::new_shape(shape));
+        assert!(!game.is_over(), "{:?} topped out on an empty board", shape);
     }
 }
@@ -0,0 +1,164 @@
+use super::tetronimo::Shape;
+use super::transform::{Orientation, Point};
+
+/// Cells a piece covers relative to its origin, indexed by shape and
+/// then orientation. Generated from `tetronimo_points` rotated with
+/// `transform::transform` and halved with `block::into_block`, which
+/// `tetronimo::geometry_tables_match_transform` checks.
+static OFFSETS: [[[(i32, i32); 4]; 4]; 7] = [
+    // O
+    [
+        [(-1, 0), (-1, -1), (0, 0), (0, -1)],
+        [(0, 0), (-1, 0), (0, -1), (-1, -1)],
+        [(0, -1), (0, 0), (-1, -1), (-1, 0)],
+        [(-1, -1), (0, -1), (-1, 0), (0, 0)],
+    ],
+    // T
+    [
+        [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        [(0, 0), (0, 1), (0, -1), (1, 0)],
+        [(0, 0), (1, 0), (-1, 0), (0, -1)],
+        [(0, 0), (0, -1), (0, 1), (-1, 0)],
+    ],
+    // I
+    [
+        [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+        [(0, 1), (0, 0), (0, -1), (0, -2)],
+        [(1, -1), (0, -1), (-1, -1), (-2, -1)],
+        [(-1, -2), (-1, -1), (-1, 0), (-1, 1)],
+    ],
+    // L
+    [
+        [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        [(0, 0), (0, 1), (0, -1), (1, -1)],
+        [(0, 0), (1, 0), (-1, 0), (-1, -1)],
+        [(0, 0), (0, -1), (0, 1), (-1, 1)],
+    ],
+    // J
+    [
+        [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        [(0, 0), (0, 1), (0, -1), (1, 1)],
+        [(0, 0), (1, 0), (-1, 0), (1, -1)],
+        [(0, 0), (0, -1), (0, 1), (-1, -1)],
+    ],
+    // S
+    [
+        [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        [(0, 0), (0, 1), (1, 0), (1, -1)],
+        [(0, 0), (1, 0), (0, -1), (-1, -1)],
+        [(0, 0), (0, -1), (-1, 0), (-1, 1)],
+    ],
+    // Z
+    [
+        [(0, 1), (-1, 1), (0, 0), (1, 0)],
+        [(1, 0), (1, 1), (0, 0), (0, -1)],
+        [(0, -1), (1, -1), (0, 0), (-1, 0)],
+        [(-1, 0), (-1, -1), (0, 0), (0, 1)],
+    ],
+];
+
+/// Smallest box around the cells of a piece, relative to its origin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+}
+
+impl Bounds {
+    pub fn width(&self) -> usize {
+        (self.max_x - self.min_x + 1) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max_y - self.min_y + 1) as usize
+    }
+}
+
+static BOUNDS: [[Bounds; 4]; 7] = [
+    // O
+    [
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 0 },
+    ],
+    // T
+    [
+        Bounds { min_x: -1, min_y: 0, max_x: 1, max_y: 1 },
+        Bounds { min_x: 0, min_y: -1, max_x: 1, max_y: 1 },
+        Bounds { min_x: -1, min_y: -1, max_x: 1, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 1 },
+    ],
+    // I
+    [
+        Bounds { min_x: -2, min_y: 0, max_x: 1, max_y: 0 },
+        Bounds { min_x: 0, min_y: -2, max_x: 0, max_y: 1 },
+        Bounds { min_x: -2, min_y: -1, max_x: 1, max_y: -1 },
+        Bounds { min_x: -1, min_y: -2, max_x: -1, max_y: 1 },
+    ],
+    // L
+    [
+        Bounds { min_x: -1, min_y: 0, max_x: 1, max_y: 1 },
+        Bounds { min_x: 0, min_y: -1, max_x: 1, max_y: 1 },
+        Bounds { min_x: -1, min_y: -1, max_x: 1, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 1 },
+    ],
+    // J
+    [
+        Bounds { min_x: -1, min_y: 0, max_x: 1, max_y: 1 },
+        Bounds { min_x: 0, min_y: -1, max_x: 1, max_y: 1 },
+        Bounds { min_x: -1, min_y: -1, max_x: 1, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 1 },
+    ],
+    // S
+    [
+        Bounds { min_x: -1, min_y: 0, max_x: 1, max_y: 1 },
+        Bounds { min_x: 0, min_y: -1, max_x: 1, max_y: 1 },
+        Bounds { min_x: -1, min_y: -1, max_x: 1, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 1 },
+    ],
+    // Z
+    [
+        Bounds { min_x: -1, min_y: 0, max_x: 1, max_y: 1 },
+        Bounds { min_x: 0, min_y: -1, max_x: 1, max_y: 1 },
+        Bounds { min_x: -1, min_y: -1, max_x: 1, max_y: 0 },
+        Bounds { min_x: -1, min_y: -1, max_x: 0, max_y: 1 },
+    ],
+];
+
+pub fn offsets(shape: Shape, orientation: Orientation) -> &'static [(i32, i32); 4] {
+    &OFFSETS[shape as usize][orientation as usize]
+}
+
+pub fn bounds(shape: Shape, orientation: Orientation) -> Bounds {
+    BOUNDS[shape as usize][orientation as usize]
+}
+
+/// The cells of a piece placed at `origin`.
+pub fn cells(shape: Shape, orientation: Orientation, origin: Point) -> [Point; 4] {
+    let o = offsets(shape, orientation);
+    [Point::new(origin.x + o[0].0, origin.y + o[0].1),
+     Point::new(origin.x + o[1].0, origin.y + o[1].1),
+     Point::new(origin.x + o[2].0, origin.y + o[2].1),
+     Point::new(origin.x + o[3].0, origin.y + o[3].1)]
+}
+
+#[test]
+fn bounds_match_offsets() {
+    for shape in &[Shape::O, Shape::T, Shape::I, Shape::L, Shape::J, Shape::S, Shape::Z] {
+        for orientation in &[Orientation::North,
+                             Orientation::East,
+                             Orientation::South,
+                             Orientation::West] {
+            let o = offsets(*shape, *orientation);
+            let b = bounds(*shape, *orientation);
+            assert_eq!(b.min_x, o.iter().map(|c| c.0).min().unwrap());
+            assert_eq!(b.min_y, o.iter().map(|c| c.1).min().unwrap());
+            assert_eq!(b.max_x, o.iter().map(|c| c.0).max().unwrap());
+            assert_eq!(b.max_y, o.iter().map(|c| c.1).max().unwrap());
+            assert!(b.width() * b.height() >= 4);
+        }
+    }
+}
@@ -29,6 +29,35 @@ pub enum Command {
     Lock,
     RotateClockwise,
     RotateCounterClockwise,
+    Hold,
+}
+
+impl Command {
+    pub fn all() -> [Command; 7] {
+        [Command::SlideLeft,
+         Command::SlideRight,
+         Command::DownFast,
+         Command::Lock,
+         Command::RotateClockwise,
+         Command::RotateCounterClockwise,
+         Command::Hold]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Command::SlideLeft => "SlideLeft",
+            Command::SlideRight => "SlideRight",
+            Command::DownFast => "DownFast",
+            Command::Lock => "Lock",
+            Command::RotateClockwise => "RotateClockwise",
+            Command::RotateCounterClockwise => "RotateCounterClockwise",
+            Command::Hold => "Hold",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Command> {
+        Command::all().iter().find(|c| c.name() == name).cloned()
+    }
 }
 
 
@@ -40,6 +69,49 @@ impl KeyMap {
     pub fn new() -> Self {
         KeyMap { map: BTreeMap::new() }
     }
+
+    /// Arrow keys, Z and space, for playing alone.
+    pub fn standard() -> Self {
+        KeyMap::with_keys(&[(Key::Up, Command::RotateClockwise),
+                            (Key::Z, Command::RotateCounterClockwise),
+                            (Key::Down, Command::DownFast),
+                            (Key::Left, Command::SlideLeft),
+                            (Key::Right, Command::SlideRight),
+                            (Key::Space, Command::Lock),
+                            (Key::LShift, Command::Hold)])
+    }
+
+    /// The left side of a shared keyboard.
+    pub fn left_player() -> Self {
+        KeyMap::with_keys(&[(Key::W, Command::RotateClockwise),
+                            (Key::Q, Command::RotateCounterClockwise),
+                            (Key::S, Command::DownFast),
+                            (Key::A, Command::SlideLeft),
+                            (Key::D, Command::SlideRight),
+                            (Key::Space, Command::Lock),
+                            (Key::LShift, Command::Hold)])
+    }
+
+    /// The right side of a shared keyboard, with no key in common with
+    /// `left_player`.
+    pub fn right_player() -> Self {
+        KeyMap::with_keys(&[(Key::Up, Command::RotateClockwise),
+                            (Key::RCtrl, Command::RotateCounterClockwise),
+                            (Key::Down, Command::DownFast),
+                            (Key::Left, Command::SlideLeft),
+                            (Key::Right, Command::SlideRight),
+                            (Key::Return, Command::Lock),
+                            (Key::RShift, Command::Hold)])
+    }
+
+    fn with_keys(keys: &[(Key, Command)]) -> Self {
+        let mut map = KeyMap::new();
+        for &(key, command) in keys {
+            map.insert(key, command);
+        }
+        map
+    }
+
     pub fn get(&self, key: &Key) -> Option<&Command> {
         self.map.get(key)
     }
@@ -55,7 +127,9 @@ pub struct CommandState {
     rotate: Option<RotateDirection>,
     drop: DropSpeed,
     lock: bool,
+    hold: bool,
     key_active: BTreeMap<Command, bool>,
+    presses: Vec<Command>,
 }
 
 impl CommandState {
@@ -64,8 +138,10 @@ impl CommandState {
             slide: None,
             rotate: None,
             lock: false,
+            hold: false,
             drop: DropSpeed::Slow,
             key_active: BTreeMap::new(),
+            presses: Vec::new(),
         }
     }
 
@@ -81,7 +157,24 @@ impl CommandState {
         self.lock
     }
 
+    /// Returns true once for every press of the hold key.
+    pub fn do_hold(&mut self) -> bool {
+        let hold = self.hold;
+        self.hold = false;
+        hold
+    }
+
+    /// Movement and rotation keys pressed since the last call,
+    /// drops and holds are not included.
+    pub fn take_presses(&mut self) -> Vec<Command> {
+        ::std::mem::replace(&mut self.presses, Vec::new())
+    }
+
     pub fn key_press(&mut self, key: Command) {
+        match key {
+            Command::DownFast | Command::Lock | Command::Hold => {}
+            _ => self.presses.push(key),
+        }
         match key {
             Command::SlideLeft => self.slide = Some(SlideDirection::Left),
             Command::SlideRight => self.slide = Some(SlideDirection::Right),
@@ -91,6 +184,7 @@ impl CommandState {
             Command::RotateCounterClockwise => {
                 self.rotate = Some(RotateDirection::CounterClockwise)
             }
+            Command::Hold => self.hold = true,
         }
         self.key_active.insert(key, true);
         if self.key_active.get(&Command::SlideLeft) == self.key_active.get(&Command::SlideRight) {
@@ -0,0 +1,69 @@
+//! A Tetris engine with everything around it: rules, bots, replays,
+//! records, network play and renderers. `env` is the interface for
+//! training agents and `perfect_clear` the solver, the `testris`
+//! binary is one frontend on top.
+
+#![feature(box_syntax)]
+
+#![feature(zero_one)]
+#![cfg_attr(test, feature(test))]
+extern crate piston_window;
+
+extern crate rand;
+
+#[macro_use]
+extern crate log;
+extern crate toml;
+extern crate rustc_serialize;
+extern crate time;
+extern crate libc;
+extern crate png;
+extern crate gif;
+
+extern crate nalgebra as na;
+#[cfg(test)]
+extern crate test;
+
+pub mod error;
+pub mod limit;
+pub mod seed;
+pub mod input;
+pub mod tetronimo;
+pub mod point;
+pub mod tetriscolor;
+pub mod transform;
+pub mod block;
+pub mod bitboard;
+pub mod board;
+pub mod game;
+pub mod geometry;
+pub mod spin;
+pub mod rules;
+pub mod score;
+pub mod font;
+pub mod stats;
+pub mod finesse;
+pub mod movegen;
+pub mod bot;
+pub mod tune;
+pub mod env;
+pub mod perfect_clear;
+pub mod versus;
+pub mod net;
+pub mod spectate;
+pub mod terminal;
+pub mod canvas;
+pub mod export;
+pub mod fumen;
+pub mod setup;
+pub mod puzzle;
+pub mod mode;
+pub mod replay;
+pub mod records;
+pub mod cli;
+pub mod config;
+#[cfg(test)]
+mod bench;
+
+
+pub type Result<T> = std::result::Result<T, error::Error>;
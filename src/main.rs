@@ -1,6 +1,3 @@
-#![feature(box_syntax)]
-
-#![feature(zero_one)]
 extern crate piston_window;
 
 extern crate rand;
@@ -8,51 +5,402 @@ extern crate rand;
 extern crate env_logger;
 #[macro_use]
 extern crate log;
-extern crate toml;
-
-extern crate nalgebra as na;
+extern crate rustc_serialize;
+extern crate time;
+extern crate testris;
 
 use piston_window::*;
-use std::ops::Add;
-use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
+use rustc_serialize::json::ToJson;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-mod error;
-mod limit;
-mod input;
-mod tetronimo;
-mod point;
-mod tetriscolor;
-mod transform;
-mod block;
-mod board;
-mod game;
+use testris::{board, bot, canvas, config, error, export, fumen, game, mode, net, perfect_clear,
+              puzzle, records, replay, rules, spectate, stats, terminal, tune, versus};
+use testris::cli::flag_value;
+use testris::Result;
 
+fn write_summary(path: &Path, stats: &stats::Statistics) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    writeln!(file, "{}", stats.to_json().pretty())
+}
 
-type Result<T> = std::result::Result<T, error::Error>;
+/// Writes the summary, logging whether it worked.
+fn save_summary(path: &Path, stats: &stats::Statistics) {
+    match write_summary(path, stats) {
+        Ok(_) => info!("Wrote game summary to {}", path.display()),
+        Err(e) => error!("Could not write game summary {}: {}", path.display(), e),
+    }
+}
 
+fn leaderboard_lines(store: &records::RecordStore, game: &game::Game) -> Vec<String> {
+    store.leaderboard(game.mode().name(), game.ruleset().name)
+        .iter()
+        .enumerate()
+        .map(|(rank, record)| record.summary(rank))
+        .collect()
+}
 
-use tetronimo::Shape;
+/// Saves the replay and adds the finished game to the records.
+fn record_game(store: &mut records::RecordStore, game: &game::Game) -> Result<Option<usize>> {
+    let date = time::strftime("%Y-%m-%d %H:%M:%S", &time::now()).unwrap();
+    let mut replay_path = String::new();
+    if let Some(dir) = records::data_dir() {
+        let dir = dir.join("replays");
+        try!(fs::create_dir_all(&dir));
+        let path = dir.join(format!("{}-{}.replay",
+                                    date.replace(" ", "_").replace(":", ""),
+                                    game.replay().seed));
+        try!(game.replay().save(&path));
+        replay_path = path.to_string_lossy().into_owned();
+    }
 
+    // An unfinished sprint has no time worth ranking, and other
+    // field sizes are not comparable with the standard one
+    if game.mode().goal_lines().is_some() && game.outcome() != Some(mode::Outcome::Completed) {
+        return Ok(None);
+    }
+    if game.board().dimensions() != board::Dimensions::standard() {
+        return Ok(None);
+    }
+    let rank = store.insert(records::Record {
+        mode: game.mode().name().to_string(),
+        ruleset: game.ruleset().name.to_string(),
+        score: game.score(),
+        lines: game.lines(),
+        time: game.statistics().time(),
+        date: date,
+        seed: game.replay().seed,
+        replay: replay_path,
+    });
+    try!(store.save());
+    Ok(rank)
+}
 
+/// Two players on one keyboard, nothing is recorded.
+fn play_versus(ruleset: rules::Ruleset, seed: u32, dimensions: board::Dimensions) {
+    let mut versus = match versus::Versus::new(ruleset, seed, dimensions) {
+        Ok(versus) => versus,
+        Err(e) => panic!("Could not start a game: {}", error::report(&e)),
+    };
+    let mut window: PistonWindow = WindowSettings::new("Tetris", versus.window_size())
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
+    let mut announced = false;
+    while let Some(e) = window.next() {
+        match e {
+            Event::Update(UpdateArgs { dt }) => {
+                versus.on_update(dt);
+                if versus.is_over() && !announced {
+                    announced = true;
+                    match versus.winner() {
+                        Some(player) => info!("Player {} wins", player + 1),
+                        None => info!("Draw"),
+                    }
+                }
+            }
+            Event::Input(ref input) => versus.on_input(input),
+            Event::Render(_) => {
+                window.draw_2d(&e, |c, g| {
+                    clear(canvas::BACKGROUND, g);
+                    versus.on_render(g, c.transform);
+                });
+            }
+            _ => debug!("Unknown Window Event {:?}", e),
+        }
+    }
+}
+
+/// Puzzles picked from a pack, nothing is recorded.
+fn play_puzzles(path: &Path, ruleset: rules::Ruleset) {
+    let loaded = puzzle::Pack::load(path).and_then(|pack| puzzle::Browser::new(pack, ruleset));
+    let mut browser = match loaded {
+        Ok(browser) => browser,
+        Err(e) => panic!("Could not load puzzles: {}", error::report(&e)),
+    };
+    let mut window: PistonWindow = WindowSettings::new("Tetris", browser.window_size())
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
+    while let Some(e) = window.next() {
+        match e {
+            Event::Update(UpdateArgs { dt }) => browser.on_update(dt),
+            Event::Input(ref input) => browser.on_input(input),
+            Event::Render(_) => {
+                window.draw_2d(&e, |c, g| {
+                    clear(canvas::BACKGROUND, g);
+                    browser.on_render(g, c.transform);
+                });
+            }
+            _ => debug!("Unknown Window Event {:?}", e),
+        }
+    }
+}
+
+/// A match against someone else on a server, nothing is recorded.
+fn play_online(address: &str) {
+    let mut net = match net::NetGame::connect(address) {
+        Ok(net) => net,
+        Err(e) => panic!("Could not connect to {}: {}", address, error::report(&e)),
+    };
+    let mut window: PistonWindow = WindowSettings::new("Tetris", net.window_size())
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
+    while let Some(e) = window.next() {
+        match e {
+            Event::Update(UpdateArgs { dt }) => {
+                if let Err(e) = net.on_update(dt) {
+                    error!("Lost the connection: {}", error::report(&e));
+                }
+            }
+            Event::Input(ref input) => {
+                if let Some(game) = net.playing() {
+                    game.on_input(input);
+                }
+            }
+            Event::Render(_) => {
+                window.draw_2d(&e, |c, g| {
+                    clear(canvas::BACKGROUND, g);
+                    net.on_render(g, c.transform);
+                });
+            }
+            _ => debug!("Unknown Window Event {:?}", e),
+        }
+    }
+}
 
 fn main() {
     env_logger::init().unwrap();
-    let mut window: PistonWindow = WindowSettings::new("Tetris", [540, 580])
+    let args: Vec<String> = std::env::args().collect();
+    let subcommand: Option<fn(&[String]) -> Result<()>> = match args.get(1).map(|s| s.as_str()) {
+        Some("records") => Some(records::run_cli),
+        Some("tune") => Some(tune::run_cli),
+        Some("pc") => Some(perfect_clear::run_cli),
+        Some("serve") => Some(net::run_server_cli),
+        Some("client") => Some(net::run_client_cli),
+        Some("watch") => Some(spectate::run_cli),
+        Some("snapshot") => Some(canvas::run_cli),
+        Some("export") => Some(export::run_cli),
+        Some("fumen") => Some(fumen::run_cli),
+        Some("puzzles") => Some(puzzle::run_cli),
+        _ => None,
+    };
+    if let Some(run) = subcommand {
+        if let Err(e) = run(&args[2..]) {
+            println!("{}", error::report(&e));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let loaded = match flag_value(&args, "--config") {
+        Some(path) => config::Config::load(&PathBuf::from(path)),
+        None => config::Config::load_default(),
+    };
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", error::report(&e));
+            std::process::exit(1);
+        }
+    };
+
+    let ruleset = match flag_value(&args, "--rules") {
+        Some(name) => {
+            match rules::Ruleset::by_name(name) {
+                Some(r) => r,
+                None => panic!("Unknown ruleset {:?}", name),
+            }
+        }
+        None => config.ruleset,
+    };
+    let mode = match flag_value(&args, "--mode") {
+        Some(name) => {
+            match mode::Mode::by_name(name) {
+                Some(m) => m,
+                None => panic!("Unknown mode {:?}", name),
+            }
+        }
+        None => config.mode,
+    };
+    let seed = match flag_value(&args, "--seed") {
+        Some(seed) => seed.parse::<u32>().expect("Seed must be a number"),
+        None => config.seed.unwrap_or_else(rand::random::<u32>),
+    };
+    if let Some(address) = flag_value(&args, "--connect") {
+        play_online(address);
+        return;
+    }
+    if args.iter().any(|a| a == "--versus") {
+        play_versus(ruleset, seed, config.dimensions);
+        return;
+    }
+    if let Some(path) = flag_value(&args, "--puzzles") {
+        play_puzzles(Path::new(path), ruleset);
+        return;
+    }
+    let summary_path = flag_value(&args, "--stats")
+        .map(PathBuf::from)
+        .unwrap_or(config.stats.clone());
+    let mut summary_written = false;
+
+    let records_path = flag_value(&args, "--records")
+        .map(PathBuf::from)
+        .or(config.records.clone())
+        .or_else(records::default_path)
+        .unwrap_or(PathBuf::from("records.toml"));
+    let mut store = match records::RecordStore::open(&records_path) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            error!("Could not load records: {}", error::report(&e));
+            None
+        }
+    };
+
+    // Watching a replay feeds its events in instead of the keyboard
+    let playback = flag_value(&args, "--replay").map(|path| {
+        match replay::Replay::load(&PathBuf::from(path)) {
+            Ok(replay) => replay,
+            Err(e) => panic!("Could not load replay: {}", error::report(&e)),
+        }
+    });
+    let mut played = 0;
+    let mut game = match playback {
+        Some(ref replay) => replay.new_game(),
+        None => {
+            match game::Game::with_dimensions(mode, ruleset, seed, config.dimensions) {
+                Ok(game) => game,
+                Err(e) => panic!("Could not start a game: {}", error::report(&e)),
+            }
+        }
+    };
+    let weights = match flag_value(&args, "--weights") {
+        Some(path) => {
+            match bot::Weights::load(&PathBuf::from(path)) {
+                Ok(weights) => weights,
+                Err(e) => panic!("Could not load bot weights: {}", error::report(&e)),
+            }
+        }
+        None => bot::Weights::default(),
+    };
+    game.set_hint_weights(weights);
+    // H toggles hints while playing, --hint starts with them on
+    if args.iter().any(|a| a == "--hint") {
+        game.toggle_hint();
+    }
+    // In demo mode the bot plays instead of the keyboard
+    let mut bot = if args.iter().any(|a| a == "--bot") {
+        let speed = flag_value(&args, "--bot-speed")
+            .map(|s| s.parse::<f64>().expect("Bot speed must be a number"))
+            .unwrap_or(10f64);
+        Some(bot::Bot::new(weights, 1, speed))
+    } else {
+        None
+    };
+    // Anyone can follow the game with `testris watch`
+    let mut broadcaster = flag_value(&args, "--spectate").map(|port| {
+        let port = port.parse::<u16>().expect("Spectator port must be a number");
+        match spectate::Broadcaster::bind(("0.0.0.0", port)) {
+            Ok(broadcaster) => broadcaster,
+            Err(e) => panic!("Could not open the spectator port: {}", error::report(&e)),
+        }
+    });
+    if broadcaster.is_some() {
+        game.enable_events();
+    }
+    // Plays in the terminal instead of a window, for SSH sessions
+    if args.iter().any(|a| a == "--terminal") {
+        let style = if args.iter().any(|a| a == "--half-blocks") {
+            terminal::Style::HalfBlocks
+        } else {
+            terminal::Style::Blocks
+        };
+        let human = playback.is_none() && bot.is_none();
+        let result = terminal::run(&mut game,
+                                   bot,
+                                   playback.as_ref(),
+                                   broadcaster.as_mut(),
+                                   style);
+        if playback.is_none() {
+            save_summary(&summary_path, game.statistics());
+        }
+        if let Err(e) = result {
+            println!("{}", error::report(&e));
+            std::process::exit(1);
+        }
+        if let (true, true, Some(ref mut store)) = (human, game.is_over(), store) {
+            if let Err(e) = record_game(store, &game) {
+                error!("Could not save records: {}", error::report(&e));
+            }
+        }
+        return;
+    }
+    let mut window: PistonWindow = WindowSettings::new("Tetris", game.window_size())
         .exit_on_esc(true)
         .build()
         .unwrap();
-    let mut game = game::Game::new();
+    if let Some(ref store) = store {
+        let lines = leaderboard_lines(store, &game);
+        game.set_leaderboard(lines);
+    }
     while let Some(e) = window.next() {
         match e {
-            Event::Update(UpdateArgs { dt }) => game.on_update(dt),
-            Event::Input(ref input) => game.on_input(input),
+            Event::Update(UpdateArgs { dt }) => {
+                match playback {
+                    Some(ref replay) => {
+                        played = replay.play_frame(&mut game, played);
+                        if let Some(ref mut broadcaster) = broadcaster {
+                            broadcaster.publish(&mut game);
+                        }
+                        continue;
+                    }
+                    None => {
+                        if let Some(ref mut bot) = bot {
+                            bot.on_update(&mut game, dt);
+                        }
+                        game.on_update(dt)
+                    }
+                }
+                if let Some(ref mut broadcaster) = broadcaster {
+                    broadcaster.publish(&mut game);
+                }
+                if game.is_over() && !summary_written {
+                    summary_written = true;
+                    save_summary(&summary_path, game.statistics());
+                    if let Some(ref mut store) = store {
+                        // Only people set records
+                        let recorded = match bot {
+                            Some(_) => Ok(None),
+                            None => record_game(store, &game),
+                        };
+                        match recorded {
+                            Ok(Some(rank)) => info!("New record, rank {}", rank + 1),
+                            Ok(None) => {}
+                            Err(e) => error!("Could not save records: {}", error::report(&e)),
+                        }
+                        let lines = leaderboard_lines(store, &game);
+                        game.set_leaderboard(lines);
+                    }
+                }
+            }
+            Event::Input(ref input) => {
+                if playback.is_none() && bot.is_none() {
+                    game.on_input(input)
+                }
+            }
             Event::Render(_) => {
                 window.draw_2d(&e, |c, g| {
-                    clear([0.5; 4], g);
+                    clear(canvas::BACKGROUND, g);
                     game.on_render(g, c.transform);
                 });
             }
             _ => debug!("Unknown Window Event {:?}", e),
         }
     }
+    // Closing the window before the game ends still leaves a summary
+    if playback.is_none() && !summary_written {
+        save_summary(&summary_path, game.statistics());
+    }
 }
@@ -0,0 +1,49 @@
+/// What the player is trying to do, which decides when a game ends
+/// and how its records are ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Play until topping out, ranked by score.
+    Marathon,
+    /// Clear 40 lines as fast as possible, ranked by time.
+    Sprint,
+}
+
+/// How a finished game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    ToppedOut,
+    Completed,
+    /// The opponent topped out first, in versus.
+    Won,
+    /// A puzzle ran out of pieces before its goal was reached.
+    Failed,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Marathon => "marathon",
+            Mode::Sprint => "sprint",
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Mode> {
+        [Mode::Marathon, Mode::Sprint].iter().find(|m| m.name() == name).cloned()
+    }
+
+    /// Lines needed to complete the mode, if it can be completed at all.
+    pub fn goal_lines(&self) -> Option<usize> {
+        match *self {
+            Mode::Marathon => None,
+            Mode::Sprint => Some(40),
+        }
+    }
+}
+
+#[test]
+fn mode_names_round_trip() {
+    for mode in &[Mode::Marathon, Mode::Sprint] {
+        assert_eq!(Some(*mode), Mode::by_name(mode.name()));
+    }
+    assert_eq!(None, Mode::by_name("zen"));
+}
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::board::GameBoard;
+use super::input::Command;
+use super::spin::{self, Spin, SpinDetection};
+use super::tetronimo::{SlideDirection, Tetromino};
+use super::transform::{Orientation, Point, RotationDirection};
+
+/// A place a piece can be locked, and how to get it there.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// The piece where it comes to rest.
+    pub piece: Tetromino,
+    /// Keys from spawn, always ending with `Lock`. A `DownFast` is held
+    /// until the piece lands, slides and rotations are single taps.
+    pub path: Vec<Command>,
+    pub spin: Option<Spin>,
+    /// Wall kick of the final rotation, if the piece did not fall after it.
+    pub kick: Option<usize>,
+}
+
+impl Placement {
+    pub fn cells(&self) -> [Point; 4] {
+        self.piece.cells()
+    }
+}
+
+/// A piece on its way down, with the move that got it there.
+struct Node {
+    piece: Tetromino,
+    parent: Option<(usize, Command)>,
+    /// Wall kick used, if the last move was a rotation.
+    kick: Option<usize>,
+}
+
+/// Every distinct resting place `spawned` can reach on `board` with
+/// slides, rotations and soft drops, which covers tucks and spins.
+/// Placements covering the same cells are merged, keeping a spin over
+/// a plain drop and otherwise the shortest path.
+pub fn placements(board: &GameBoard,
+                  spawned: &Tetromino,
+                  detection: SpinDetection)
+                  -> Vec<Placement> {
+    if !board.check_piece(spawned) {
+        return Vec::new();
+    }
+
+    let mut nodes = vec![Node {
+                             piece: spawned.clone(),
+                             parent: None,
+                             kick: None,
+                         }];
+    let mut seen = HashSet::new();
+    seen.insert(key(spawned, None));
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+
+    let mut found: Vec<Placement> = Vec::new();
+    let mut by_cells: HashMap<[(i32, i32); 4], usize> = HashMap::new();
+
+    while let Some(current) = queue.pop_front() {
+        let cells = sorted_cells(&board.drop_piece(&nodes[current].piece));
+        match by_cells.get(&cells).cloned() {
+            // Only a spin can beat the shorter path found first
+            Some(i) => {
+                if found[i].spin.is_none() && nodes[current].kick.is_some() {
+                    let placement = lock(board, &nodes, current, detection);
+                    if placement.spin.is_some() {
+                        found[i] = placement;
+                    }
+                }
+            }
+            None => {
+                by_cells.insert(cells, found.len());
+                found.push(lock(board, &nodes, current, detection));
+            }
+        }
+
+        for (command, piece, kick) in moves(board, &nodes[current].piece) {
+            if seen.insert(key(&piece, kick)) {
+                nodes.push(Node {
+                    piece: piece,
+                    parent: Some((current, command)),
+                    kick: kick,
+                });
+                queue.push_back(nodes.len() - 1);
+            }
+        }
+    }
+    found
+}
+
+/// States only differ if they would lock or spin differently.
+fn key(piece: &Tetromino, kick: Option<usize>) -> (i32, i32, Orientation, Option<usize>) {
+    let origin = piece.origin();
+    (origin.x, origin.y, piece.orientation(), kick)
+}
+
+fn moves(board: &GameBoard, piece: &Tetromino) -> Vec<(Command, Tetromino, Option<usize>)> {
+    let mut result = Vec::with_capacity(5);
+    for &(command, direction) in &[(Command::SlideLeft, SlideDirection::Left),
+                                   (Command::SlideRight, SlideDirection::Right)] {
+        if let Some(slid) = board.slide_piece(piece, direction) {
+            result.push((command, slid, None));
+        }
+    }
+    for &(command, direction) in &[(Command::RotateClockwise, RotationDirection::Clockwise),
+                                   (Command::RotateCounterClockwise,
+                                    RotationDirection::CounterClockwise)] {
+        if let Some((rotated, kick)) = board.rotate_piece(piece, direction) {
+            result.push((command, rotated, Some(kick)));
+        }
+    }
+    let dropped = board.drop_piece(piece);
+    if dropped.origin() != piece.origin() {
+        result.push((Command::DownFast, dropped, None));
+    }
+    result
+}
+
+/// Hard drops the piece of a node, like the game does on `Lock`.
+fn lock(board: &GameBoard, nodes: &[Node], index: usize, detection: SpinDetection) -> Placement {
+    let node = &nodes[index];
+    let landed = board.drop_piece(&node.piece);
+    // Falling any distance counts as a move after the rotation
+    let kick = if landed.origin() == node.piece.origin() {
+        node.kick
+    } else {
+        None
+    };
+    let spin = kick.and_then(|kick| spin::detect(board, &landed, kick, detection));
+
+    let mut path = vec![Command::Lock];
+    let mut current = index;
+    while let Some((parent, command)) = nodes[current].parent {
+        path.push(command);
+        current = parent;
+    }
+    path.reverse();
+
+    Placement {
+        piece: landed,
+        path: path,
+        spin: spin,
+        kick: kick,
+    }
+}
+
+fn sorted_cells(piece: &Tetromino) -> [(i32, i32); 4] {
+    let c = piece.cells();
+    let mut cells = [(c[0].x, c[0].y), (c[1].x, c[1].y), (c[2].x, c[2].y), (c[3].x, c[3].y)];
+    cells.sort();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Block;
+    use board::{GameBoard, Pixel};
+    use input::Command;
+    use spin::{SpinDetection, SpinKind};
+    use tetriscolor::Color;
+    use tetronimo::{Shape, Tetromino};
+    use transform::Point;
+
+    fn board() -> GameBoard {
+        GameBoard::new(10, 22, 2, Pixel::new(0f64, 0f64))
+    }
+
+    fn spawn(shape: Shape) -> Tetromino {
+        let mut piece = Tetromino::new_shape(shape);
+        piece.put(Point::new(5, 20));
+        piece
+    }
+
+    fn fill(board: &mut GameBoard, cells: &[(i32, i32)]) {
+        let blocks: Vec<Block> = cells.iter()
+            .map(|&(x, y)| Block::new(Color::black(), Point::new(x, y)))
+            .collect();
+        board.add_blocks(&blocks);
+    }
+
+    #[test]
+    fn empty_board_counts() {
+        // Flat drops only: columns times distinct orientations
+        assert_eq!(9, placements(&board(), &spawn(Shape::O), SpinDetection::TSpin).len());
+        assert_eq!(17, placements(&board(), &spawn(Shape::I), SpinDetection::TSpin).len());
+        assert_eq!(34, placements(&board(), &spawn(Shape::T), SpinDetection::TSpin).len());
+        assert_eq!(17, placements(&board(), &spawn(Shape::S), SpinDetection::TSpin).len());
+    }
+
+    #[test]
+    fn every_path_reaches_its_placement() {
+        let mut board = board();
+        fill(&mut board, &[(0, 0), (1, 0), (2, 0), (0, 1), (7, 0), (8, 0), (8, 1), (9, 0)]);
+        for placement in placements(&board, &spawn(Shape::L), SpinDetection::TSpin) {
+            let mut piece = spawn(Shape::L);
+            for command in &placement.path {
+                piece = match *command {
+                    Command::SlideLeft => board.slide_piece(&piece, SlideDirection::Left).unwrap(),
+                    Command::SlideRight => {
+                        board.slide_piece(&piece, SlideDirection::Right).unwrap()
+                    }
+                    Command::RotateClockwise => {
+                        board.rotate_piece(&piece, RotationDirection::Clockwise).unwrap().0
+                    }
+                    Command::RotateCounterClockwise => {
+                        board.rotate_piece(&piece, RotationDirection::CounterClockwise)
+                            .unwrap()
+                            .0
+                    }
+                    Command::DownFast | Command::Lock => board.drop_piece(&piece),
+                    Command::Hold => unreachable!(),
+                };
+            }
+            assert_eq!(placement.cells(), piece.cells());
+            assert_eq!(Some(&Command::Lock), placement.path.last());
+        }
+    }
+
+    #[test]
+    fn finds_a_t_spin_double_under_an_overhang() {
+        // Row 0 is missing x = 2, row 1 is missing x = 1..3,
+        // and the block at (1, 2) overhangs the slot
+        let mut board = board();
+        let mut stack = vec![(0, 1), (1, 2)];
+        for x in 0..10 {
+            if x != 2 {
+                stack.push((x, 0));
+            }
+            if x > 3 {
+                stack.push((x, 1));
+            }
+        }
+        fill(&mut board, &stack);
+        let tsd = placements(&board, &spawn(Shape::T), SpinDetection::TSpin)
+            .into_iter()
+            .find(|p| p.spin.map(|s| s.kind) == Some(SpinKind::Full))
+            .unwrap();
+        let mut cells: Vec<(i32, i32)> = tsd.cells().iter().map(|p| (p.x, p.y)).collect();
+        cells.sort();
+        assert_eq!(vec![(1, 1), (2, 0), (2, 1), (3, 1)], cells);
+        let last_move = tsd.path[tsd.path.len() - 2];
+        assert!(last_move == Command::RotateClockwise ||
+                last_move == Command::RotateCounterClockwise);
+    }
+
+    #[test]
+    fn nothing_when_spawn_is_blocked() {
+        let mut board = board();
+        fill(&mut board, &[(5, 20)]);
+        assert!(placements(&board, &spawn(Shape::T), SpinDetection::TSpin).is_empty());
+    }
+}
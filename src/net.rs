@@ -0,0 +1,581 @@
+use piston_window::*;
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::bitboard::BitBoard;
+use super::board::Dimensions;
+use super::bot::{Bot, Weights};
+use super::cli;
+use super::env::FRAME;
+use super::error::{self, Error, ParseError};
+use super::game::Game;
+use super::mode::{Mode, Outcome};
+use super::rules::Ruleset;
+use super::Result;
+
+pub const PORT: u16 = 7878;
+pub const VERSION: u32 = 2;
+const HEADER: &'static str = "testris-net";
+/// Round trips timed to each player before the start, the shortest counts.
+const PINGS: u64 = 3;
+/// How long a new connection has to say hello before it is turned away.
+const HELLO_TIMEOUT: u64 = 2;
+/// Size of a cell when drawing the opponent's board.
+const OPPONENT_UNIT: f64 = 20.0;
+
+/// One line of the protocol. Clients say hello, and once two have joined
+/// the server times a few pings to each and sends both a start. From
+/// then on it passes everything a client sends on to the other one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// `testris-net VERSION`, the first line a client sends.
+    Hello(u32),
+    /// `ping N`, answered straight away with the same number.
+    Ping(u64),
+    /// `pong N`
+    Pong(u64),
+    /// `start SEED COUNTDOWN RULES`, both clients start the same game
+    /// once the countdown in seconds has run out. Each countdown is
+    /// shortened by the time the message takes to arrive.
+    Start(u32, f64, Ruleset),
+    /// `attack LINES`, garbage for the opponent.
+    Attack(usize),
+    /// `board WIDTH ROW...`, the stack after a lock, rows as hex from
+    /// the bottom up.
+    Board(BitBoard),
+    /// `over`, the sender has topped out.
+    Over,
+}
+
+impl Message {
+    pub fn parse(line: &str) -> Result<Message> {
+        let bad = |message: &str| {
+            Error::BadMessage(ParseError::new(format!("{}: {:?}", message, line)))
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| words.get(i).and_then(|w| w.parse::<u64>().ok());
+        match words.first().cloned() {
+            Some(HEADER) => {
+                number(1).map(|v| Message::Hello(v as u32)).ok_or_else(|| bad("missing version"))
+            }
+            Some("ping") => number(1).map(Message::Ping).ok_or_else(|| bad("missing number")),
+            Some("pong") => number(1).map(Message::Pong).ok_or_else(|| bad("missing number")),
+            Some("start") => {
+                let countdown = words.get(2).and_then(|w| w.parse::<f64>().ok());
+                let ruleset = words.get(3).and_then(|w| Ruleset::by_name(w));
+                match (number(1), countdown, ruleset) {
+                    (Some(seed), Some(countdown), Some(ruleset)) => {
+                        Ok(Message::Start(seed as u32, countdown, ruleset))
+                    }
+                    _ => Err(bad("expected a seed, countdown and rules")),
+                }
+            }
+            Some("attack") => {
+                number(1).map(|n| Message::Attack(n as usize)).ok_or_else(|| bad("missing lines"))
+            }
+            Some("board") => {
+                let width = try!(number(1)
+                    .map(|w| w as usize)
+                    .and_then(|w| if w > 0 && w <= 64 { Some(w) } else { None })
+                    .ok_or_else(|| bad("bad width")));
+                let mut board = BitBoard::new(width, words.len() - 2);
+                for (y, word) in words[2..].iter().enumerate() {
+                    let row = try!(u64::from_str_radix(word, 16).map_err(|_| bad("bad row")));
+                    for x in (0..width).filter(|x| row & (1 << x) != 0) {
+                        board.set(x, y);
+                    }
+                }
+                Ok(Message::Board(board))
+            }
+            Some("over") => Ok(Message::Over),
+            _ => Err(bad("unknown message")),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Message::Hello(version) => write!(f, "{} {}", HEADER, version),
+            Message::Ping(n) => write!(f, "ping {}", n),
+            Message::Pong(n) => write!(f, "pong {}", n),
+            Message::Start(seed, countdown, ruleset) => {
+                write!(f, "start {} {} {}", seed, countdown, ruleset.name)
+            }
+            Message::Attack(lines) => write!(f, "attack {}", lines),
+            Message::Board(ref board) => {
+                try!(write!(f, "board {}", board.width()));
+                for y in 0..board.height() {
+                    try!(write!(f, " {:x}", board.row(y)));
+                }
+                Ok(())
+            }
+            Message::Over => write!(f, "over"),
+        }
+    }
+}
+
+fn send(stream: &mut TcpStream, message: &Message) -> Result<()> {
+    try!(writeln!(stream, "{}", message));
+    Ok(())
+}
+
+/// The next message, `None` once the other end has hung up.
+fn receive<R: BufRead>(reader: &mut R) -> Result<Option<Message>> {
+    let mut line = String::new();
+    if try!(reader.read_line(&mut line)) == 0 {
+        return Ok(None);
+    }
+    Message::parse(line.trim()).map(Some)
+}
+
+/// Waits for two players, starts their game and relays messages
+/// between them until both have left.
+pub fn serve(listener: &TcpListener, seed: u32, countdown: f64, ruleset: Ruleset) -> Result<()> {
+    let mut players = Vec::new();
+    while players.len() < 2 {
+        let (stream, address) = try!(listener.accept());
+        try!(stream.set_nodelay(true));
+        // Someone who never speaks would keep everyone else waiting, the
+        // timeout stays on until the pings have been answered
+        try!(stream.set_read_timeout(Some(Duration::from_secs(HELLO_TIMEOUT))));
+        let mut reader = BufReader::new(try!(stream.try_clone()));
+        match receive(&mut reader) {
+            Ok(Some(Message::Hello(VERSION))) => {
+                info!("Player {} joined from {}", players.len() + 1, address);
+                players.push((reader, stream));
+            }
+            Ok(other) => error!("Turned away {}, it said {:?}", address, other),
+            Err(e) => error!("Turned away {}: {}", address, error::report(&e)),
+        }
+    }
+
+    // Starts arrive late by half a round trip, so that much is left
+    // off each countdown for both to end together
+    let mut delays = Vec::new();
+    for &mut (ref mut reader, ref mut stream) in &mut players {
+        delays.push(try!(round_trip(reader, stream)) / 2f64);
+    }
+    for (&mut (_, ref mut stream), delay) in players.iter_mut().zip(delays) {
+        info!("Starting a player {:.1}ms away", delay * 1000f64);
+        try!(stream.set_read_timeout(None));
+        try!(send(stream, &Message::Start(seed, (countdown - delay).max(0f64), ruleset)));
+    }
+    let (second_reader, second) = players.pop().unwrap();
+    let (first_reader, first) = players.pop().unwrap();
+    let relays = vec![relay(first_reader, try!(second.try_clone())),
+                      relay(second_reader, try!(first.try_clone()))];
+    for relay in relays {
+        let _ = relay.join();
+    }
+    Ok(())
+}
+
+/// The shortest of a few round trips to a player, in seconds.
+fn round_trip(reader: &mut BufReader<TcpStream>, stream: &mut TcpStream) -> Result<f64> {
+    let mut best = ::std::f64::INFINITY;
+    for n in 0..PINGS {
+        let sent = Instant::now();
+        try!(send(stream, &Message::Ping(n)));
+        loop {
+            match try!(receive(reader)) {
+                Some(Message::Pong(m)) if m == n => break,
+                Some(other) => error!("Expected pong {}, got {:?}", n, other),
+                None => {
+                    return Err(Error::Io(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                                        "a player left before the start")))
+                }
+            }
+        }
+        let elapsed = sent.elapsed();
+        best = best.min(elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9);
+    }
+    Ok(best)
+}
+
+/// Passes on game messages from one player to the other. When the first
+/// one leaves, the other one hears about it.
+fn relay(mut from: BufReader<TcpStream>, mut to: TcpStream) -> JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            match receive(&mut from) {
+                Ok(Some(Message::Hello(_))) |
+                Ok(Some(Message::Ping(_))) |
+                Ok(Some(Message::Pong(_))) |
+                Ok(Some(Message::Start(..))) => error!("Ignored a handshake during the game"),
+                Ok(Some(message)) => {
+                    if send(&mut to, &message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(Error::Io(e)) => {
+                    error!("Lost a player: {}", e);
+                    break;
+                }
+                Err(e) => error!("Dropped a message: {}", error::report(&e)),
+            }
+        }
+        let _ = to.shutdown(Shutdown::Write);
+    })
+}
+
+/// A connection to a server. Messages are read on a thread of their
+/// own, so a slow network never holds up the game.
+pub struct Client {
+    stream: TcpStream,
+    inbox: Receiver<Message>,
+    connected: bool,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Client> {
+        let mut stream = try!(TcpStream::connect(address));
+        try!(stream.set_nodelay(true));
+        try!(send(&mut stream, &Message::Hello(VERSION)));
+        let mut reader = BufReader::new(try!(stream.try_clone()));
+        // Pings only come before the start, while the game sends nothing
+        let mut answer = try!(stream.try_clone());
+        let (sender, inbox) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                match receive(&mut reader) {
+                    // Answered here, a frame's wait would count as latency
+                    Ok(Some(Message::Ping(n))) => {
+                        if send(&mut answer, &Message::Pong(n)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Some(message)) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(Error::Io(e)) => {
+                        error!("Lost the server: {}", e);
+                        break;
+                    }
+                    Err(e) => error!("Dropped a message: {}", error::report(&e)),
+                }
+            }
+        });
+        Ok(Client {
+            stream: stream,
+            inbox: inbox,
+            connected: true,
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<()> {
+        send(&mut self.stream, message)
+    }
+
+    /// Everything that arrived since the last call, without waiting.
+    pub fn receive(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        loop {
+            match self.inbox.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    break;
+                }
+            }
+        }
+        messages
+    }
+
+    /// False once the server has hung up.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // The reader thread has a handle on the socket too, this is
+        // what makes it stop
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// One side of a network match: the local game plus what is known
+/// about the opponent.
+pub struct NetGame {
+    client: Client,
+    /// Made when the server says start.
+    game: Option<Game>,
+    /// Seconds left before play starts.
+    countdown: f64,
+    /// The opponent's stack as of their last lock.
+    opponent: Option<BitBoard>,
+    /// Pieces locked when the board was last sent.
+    pieces: usize,
+    sent_over: bool,
+}
+
+impl NetGame {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<NetGame> {
+        Ok(NetGame {
+            client: try!(Client::connect(address)),
+            game: None,
+            countdown: 0f64,
+            opponent: None,
+            pieces: 0,
+            sent_over: false,
+        })
+    }
+
+    pub fn game(&self) -> Option<&Game> {
+        self.game.as_ref()
+    }
+
+    /// The local game, once the countdown is over.
+    pub fn playing(&mut self) -> Option<&mut Game> {
+        if self.countdown > 0f64 {
+            return None;
+        }
+        match self.game {
+            Some(ref mut game) if !game.is_over() => Some(game),
+            _ => None,
+        }
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.game.as_ref().and_then(|game| game.outcome())
+    }
+
+    /// Handles what the opponent did, advances the local game and tells
+    /// the opponent about it.
+    pub fn on_update(&mut self, dt: f64) -> Result<()> {
+        for message in self.client.receive() {
+            self.on_message(message);
+        }
+        let mut game = match self.game.take() {
+            Some(game) => game,
+            None => return Ok(()),
+        };
+        let result = self.advance(&mut game, dt);
+        self.game = Some(game);
+        result
+    }
+
+    fn on_message(&mut self, message: Message) {
+        if let Message::Start(seed, countdown, ruleset) = message {
+            if self.game.is_none() {
+                info!("Starting game {} with {} rules", seed, ruleset.name);
+                let mut game = Game::new(Mode::Marathon, ruleset, seed);
+                game.announce(&format!("{}", countdown.ceil()));
+                self.countdown = countdown;
+                self.game = Some(game);
+                return;
+            }
+        }
+        match (message, self.game.as_mut()) {
+            (Message::Attack(lines), Some(game)) => game.receive_garbage(lines),
+            (Message::Board(board), _) => self.opponent = Some(board),
+            (Message::Over, Some(game)) => game.win(),
+            (message, _) => error!("Unexpected {:?}", message),
+        }
+    }
+
+    fn advance(&mut self, game: &mut Game, dt: f64) -> Result<()> {
+        // An opponent who leaves early gives up
+        if !self.client.is_connected() {
+            game.win();
+        }
+        if self.countdown > 0f64 {
+            let shown = self.countdown.ceil();
+            self.countdown -= dt;
+            if self.countdown <= 0f64 {
+                game.announce("Go");
+            } else if self.countdown.ceil() != shown {
+                game.announce(&format!("{}", self.countdown.ceil()));
+            }
+            return Ok(());
+        }
+
+        game.on_update(dt);
+        let attack = game.take_attack();
+        if attack > 0 {
+            try!(self.client.send(&Message::Attack(attack)));
+        }
+        if game.statistics().pieces() != self.pieces {
+            self.pieces = game.statistics().pieces();
+            try!(self.client.send(&Message::Board(game.board().bits().clone())));
+        }
+        if game.outcome() == Some(Outcome::ToppedOut) && !self.sent_over {
+            self.sent_over = true;
+            try!(self.client.send(&Message::Over));
+        }
+        Ok(())
+    }
+
+    /// Room for the local game and the opponent's board to its right.
+    pub fn window_size(&self) -> [u32; 2] {
+        let size = Game::window_size_for(Dimensions::standard());
+        let opponent = (Dimensions::standard().width + 1) as f64 * OPPONENT_UNIT;
+        [size[0] + opponent as u32, size[1]]
+    }
+
+    pub fn on_render<G>(&self, g: &mut G, view: math::Matrix2d)
+        where G: Graphics
+    {
+        let game = match self.game {
+            Some(ref game) => game,
+            None => return,
+        };
+        game.on_render(g, view);
+
+        let d = Dimensions::standard();
+        let x = game.window_size()[0] as f64;
+        let top = 40f64;
+        let backdrop = [x, top, d.width as f64 * OPPONENT_UNIT, d.height as f64 * OPPONENT_UNIT];
+        rectangle([0f32, 0f32, 0f32, 0.5f32], backdrop, view, g);
+        if let Some(ref board) = self.opponent {
+            for y in 0..d.height.min(board.height()) {
+                for cx in (0..board.width()).filter(|&cx| board.row(y) & (1 << cx) != 0) {
+                    let cell = rectangle::square(x + cx as f64 * OPPONENT_UNIT,
+                                                 top + (d.height - 1 - y) as f64 * OPPONENT_UNIT,
+                                                 OPPONENT_UNIT * 0.95);
+                    rectangle([0.7f32, 0.7f32, 0.7f32, 1f32], cell, view, g);
+                }
+            }
+        }
+    }
+}
+
+/// Plays a whole match without a window, with `bot` at the keys or
+/// nobody at all. With `realtime` off the game runs as fast as it can.
+pub fn play_headless<A: ToSocketAddrs>(address: A,
+                                       mut bot: Option<Bot>,
+                                       realtime: bool)
+                                       -> Result<Outcome> {
+    let mut net = try!(NetGame::connect(address));
+    loop {
+        if let (Some(bot), Some(game)) = (bot.as_mut(), net.playing()) {
+            bot.on_update(game, FRAME);
+        }
+        try!(net.on_update(FRAME));
+        if let Some(outcome) = net.outcome() {
+            return Ok(outcome);
+        }
+        if net.game().is_none() && !net.client.is_connected() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                                "the server closed before the game started")));
+        }
+        // Waiting for the other player is not worth spinning for
+        if realtime || net.game().is_none() {
+            thread::sleep(Duration::from_millis(if realtime { 16 } else { 1 }));
+        }
+    }
+}
+
+/// `testris serve`, hosts one match.
+pub fn run_server_cli(args: &[String]) -> Result<()> {
+    if args.iter().any(|a| a == "--help") {
+        println!("usage: testris serve [--port N] [--seed N] [--countdown SECONDS] [--rules NAME]");
+        return Ok(());
+    }
+    let port = try!(cli::number_flag(args, "--port", PORT));
+    let seed = try!(cli::number_flag(args, "--seed", ::rand::random::<u32>()));
+    let countdown = try!(cli::number_flag(args, "--countdown", 3f64));
+    let ruleset = match cli::flag_value(args, "--rules") {
+        Some(name) => {
+            try!(Ruleset::by_name(name).ok_or_else(|| {
+                Error::Config(ParseError::new(format!("unknown rules {:?}", name)))
+            }))
+        }
+        None => Ruleset::guideline(),
+    };
+    let listener = try!(TcpListener::bind(("0.0.0.0", port)));
+    println!("Waiting for two players on port {}", port);
+    serve(&listener, seed, countdown, ruleset)
+}
+
+/// `testris client`, joins a match without a window and lets the bot play.
+pub fn run_client_cli(args: &[String]) -> Result<()> {
+    let address = match args.first() {
+        Some(address) if !address.starts_with("--") => address.clone(),
+        _ => {
+            println!("usage: testris client HOST:PORT [--idle] [--weights FILE] [--bot-speed N]");
+            return Ok(());
+        }
+    };
+    let weights = match cli::flag_value(args, "--weights") {
+        Some(path) => try!(Weights::load(&PathBuf::from(path))),
+        None => Weights::default(),
+    };
+    let speed = try!(cli::number_flag(args, "--bot-speed", 10f64));
+    // An idle client just lets pieces fall, which is handy for testing
+    let bot = if args.iter().any(|a| a == "--idle") {
+        None
+    } else {
+        Some(Bot::new(weights, 1, speed))
+    };
+    let outcome = try!(play_headless(address.as_str(), bot, true));
+    println!("{}",
+             match outcome {
+                 Outcome::Won => "Won",
+                 _ => "Lost",
+             });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitboard::BitBoard;
+    use bot::{Bot, Weights};
+    use mode::Outcome;
+    use rules::Ruleset;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn messages_round_trip() {
+        let mut board = BitBoard::new(10, 3);
+        board.set(0, 0);
+        board.set(9, 2);
+        let messages = [Message::Hello(VERSION),
+                        Message::Ping(3),
+                        Message::Pong(3),
+                        Message::Start(12, 2.5, Ruleset::all_spin()),
+                        Message::Attack(4),
+                        Message::Board(board),
+                        Message::Over];
+        for message in messages.iter() {
+            assert_eq!(*message, Message::parse(&message.to_string()).unwrap());
+        }
+        let bad = ["", "attack", "start 1 x guideline", "board 10 zz", "board 99 0", "ping x",
+                   "dance"];
+        for line in &bad {
+            assert!(Message::parse(line).is_err(), "{:?} parsed", line);
+        }
+    }
+
+    #[test]
+    fn local_match_has_a_winner() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // Turned away once it has kept quiet for too long
+        let _silent = TcpStream::connect(address).unwrap();
+        let server = thread::spawn(move || serve(&listener, 5, 0.5, Ruleset::guideline()));
+
+        let bot = Bot::new(Weights::default(), 0, 60f64);
+        let player = thread::spawn(move || play_headless(address, Some(bot), false));
+        let idle = thread::spawn(move || play_headless(address, None, false));
+
+        assert_eq!(Outcome::ToppedOut, idle.join().unwrap().unwrap());
+        assert_eq!(Outcome::Won, player.join().unwrap().unwrap());
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use super::bitboard::BitBoard;
+use super::board::GameBoard;
+use super::cli;
+use super::fumen;
+use super::game::Game;
+use super::movegen::{self, Placement};
+use super::replay::Replay;
+use super::setup::{self, Setup};
+use super::spin::SpinDetection;
+use super::tetronimo::{Shape, Tetromino};
+use super::transform::Point;
+use super::Result;
+
+/// One piece of a solution.
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// True if hold was pressed before placing this piece.
+    pub hold: bool,
+    /// Where the piece goes on the board as it is by then, after the
+    /// lines cleared by earlier steps.
+    pub placement: Placement,
+}
+
+impl Step {
+    pub fn shape(&self) -> Shape {
+        self.placement.piece.shape()
+    }
+}
+
+/// Start a piece in the empty rows just above the `rows` being
+/// filled. Open air is the same at any height, so this reaches the same
+/// placements as the real spawn point while searching far fewer moves.
+fn spawn(board: &GameBoard, shape: Shape, rows: usize) -> Tetromino {
+    let d = board.dimensions();
+    let top = d.height + d.hidden - 2;
+    let mut piece = Tetromino::new_shape(shape);
+    piece.put(Point::new((d.width / 2) as i32, (rows + 2).min(top) as i32));
+    piece
+}
+
+/// A search for perfect clears using at most `height` rows.
+struct Search<'a> {
+    queue: &'a [Shape],
+    height: usize,
+    limit: Option<usize>,
+    solutions: Vec<Vec<Step>>,
+    /// Positions already known not to lead anywhere.
+    dead: HashSet<(BitBoard, Option<Shape>, usize, usize)>,
+}
+
+impl<'a> Search<'a> {
+    fn is_done(&self) -> bool {
+        self.limit.map(|limit| self.solutions.len() >= limit).unwrap_or(false)
+    }
+
+    /// Returns true if at least one solution was found from here.
+    fn run(&mut self,
+           board: &GameBoard,
+           hold: Option<Shape>,
+           next: usize,
+           cleared: usize,
+           steps: &mut Vec<Step>)
+           -> bool {
+        if board.is_empty() && !steps.is_empty() {
+            self.solutions.push(steps.clone());
+            return true;
+        }
+        let key = (board.bits().clone(), hold, next, cleared);
+        if self.dead.contains(&key) {
+            return false;
+        }
+        // Not enough pieces left to fill the rows
+        let rows = self.height - cleared;
+        let empty = rows * board.width() - board.bits().count();
+        let pieces = self.queue.len().saturating_sub(next) + hold.map(|_| 1).unwrap_or(0);
+        if empty > pieces * 4 {
+            self.dead.insert(key);
+            return false;
+        }
+
+        // Each option is the piece to place, whether hold was used,
+        // the new hold and the next unused queue index
+        let mut options = Vec::new();
+        match (self.queue.get(next).cloned(), hold) {
+            (Some(current), Some(held)) => {
+                options.push((current, false, hold, next + 1));
+                if held != current {
+                    options.push((held, true, Some(current), next + 1));
+                }
+            }
+            (Some(current), None) => {
+                options.push((current, false, None, next + 1));
+                match self.queue.get(next + 1) {
+                    Some(&after) if after != current => {
+                        options.push((after, true, Some(current), next + 2))
+                    }
+                    _ => {}
+                }
+            }
+            // Swapping in the held piece puts an unknown one in hold
+            (None, Some(held)) => options.push((held, true, None, next)),
+            (None, None) => {}
+        }
+
+        let mut found = false;
+        for (shape, held, hold, next) in options {
+            let piece = spawn(board, shape, rows);
+            for placement in movegen::placements(board, &piece, SpinDetection::TSpin) {
+                if placement.cells().iter().any(|p| p.y as usize >= rows) {
+                    continue;
+                }
+                let mut after = board.clone();
+                after.add_blocks(&placement.piece.blocks());
+                let lines = after.wipe_full_rows();
+                if !fillable(after.bits(), rows - lines) {
+                    continue;
+                }
+                steps.push(Step {
+                    hold: held,
+                    placement: placement,
+                });
+                found |= self.run(&after, hold, next, cleared + lines, steps);
+                steps.pop();
+                if self.is_done() {
+                    return true;
+                }
+            }
+        }
+        if !found {
+            self.dead.insert(key);
+        }
+        found
+    }
+}
+
+/// Quick check that the empty cells below `rows` could still be covered
+/// by whole pieces: every region walled off by full columns needs a
+/// multiple of four empty cells.
+fn fillable(bits: &BitBoard, rows: usize) -> bool {
+    let mut empty = 0;
+    for x in 0..bits.width() {
+        let column = (0..rows).filter(|&y| !bits.is_occupied(x as i32, y as i32)).count();
+        if column == 0 {
+            if empty % 4 != 0 {
+                return false;
+            }
+            empty = 0;
+        }
+        empty += column;
+    }
+    empty % 4 == 0 && (rows..bits.height()).all(|y| bits.row(y) == 0)
+}
+
+/// Every way to clear the whole board using the pieces of `queue` in
+/// order, starting with `hold` in the hold box, without stacking above
+/// `height` rows. Pieces move with the real kicks, so tucks and spins
+/// are found too. Stops early once `limit` solutions were found, which
+/// is worth setting for longer queues: a ten piece opener has thousands.
+pub fn solve(board: &GameBoard,
+             hold: Option<Shape>,
+             queue: &[Shape],
+             height: usize,
+             limit: Option<usize>)
+             -> Vec<Vec<Step>> {
+    if height == 0 || height > board.height() || !fillable(board.bits(), height) {
+        return Vec::new();
+    }
+    let mut search = Search {
+        queue: queue,
+        height: height,
+        limit: limit,
+        solutions: Vec::new(),
+        dead: HashSet::new(),
+    };
+    search.run(board, hold, 0, 0, &mut Vec::new());
+    search.solutions
+}
+
+/// Solves from a position in a game, with the active piece and the
+/// first `preview` pieces of its queue.
+pub fn solve_game(game: &Game,
+                  preview: usize,
+                  height: usize,
+                  limit: Option<usize>)
+                  -> Vec<Vec<Step>> {
+    solve(game.board(), game.held(), &queue_of(game, preview), height, limit)
+}
+
+fn queue_of(game: &Game, preview: usize) -> Vec<Shape> {
+    let mut queue = vec![game.active_piece().shape()];
+    queue.extend((0..preview).filter_map(|i| game.preview(i)).map(|p| p.shape()));
+    queue
+}
+
+/// The solution drawn as rows, each cell showing the piece that filled
+/// it before any lines were cleared. Rows that were already full are
+/// left as they were.
+pub fn draw(board: &GameBoard, solution: &[Step], height: usize) -> Vec<String> {
+    let width = board.width();
+    let mut cells: Vec<Vec<char>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| if board.is_occupied(&Point::new(x as i32, y as i32)) {
+                    '#'
+                } else {
+                    '.'
+                })
+                .collect()
+        })
+        .collect();
+    // Rows are cleared as the solution goes, so keep track of where
+    // each row of the board started out
+    let mut rows: Vec<usize> = (0..height).collect();
+    for step in solution {
+        let (open, full): (Vec<usize>, Vec<usize>) =
+            rows.into_iter().partition(|&row| cells[row].contains(&'.'));
+        rows = open.into_iter().chain(full).collect();
+        for p in step.placement.cells().iter() {
+            cells[rows[p.y as usize]][p.x as usize] = step.shape().letter();
+        }
+    }
+    cells.iter().rev().map(|row| row.iter().collect()).collect()
+}
+
+/// `testris pc`, prints every perfect clear for a board and queue.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let height = try!(cli::number_flag(args, "--height", 4usize));
+    let limit = match cli::flag_value(args, "--max") {
+        Some(_) => Some(try!(cli::number_flag(args, "--max", 0usize))),
+        None => None,
+    };
+    let hold = match cli::flag_value(args, "--hold") {
+        Some(letter) => try!(setup::parse_queue(letter)).first().cloned(),
+        None => None,
+    };
+    let queue = match cli::flag_value(args, "--queue") {
+        Some(text) => Some(try!(setup::parse_queue(text))),
+        None => None,
+    };
+
+    if let Some(path) = cli::flag_value(args, "--board") {
+        let setup = try!(Setup::load(&PathBuf::from(path), 20));
+        return report(&setup.board,
+                      hold.or(setup.hold),
+                      &queue.unwrap_or(setup.queue),
+                      height,
+                      limit);
+    }
+    if let Some(text) = cli::flag_value(args, "--fumen") {
+        // The first page, its quiz comment giving the pieces
+        let page = try!(fumen::decode(text)).remove(0);
+        let quiz = page.queue();
+        let queue = queue.unwrap_or_else(|| {
+            quiz.as_ref().map_or(Vec::new(), |q| q.current.iter().chain(&q.next).cloned().collect())
+        });
+        let hold = hold.or(quiz.and_then(|q| q.hold));
+        return report(&page.board, hold, &queue, height, limit);
+    }
+    if let Some(path) = cli::flag_value(args, "--replay") {
+        // The position after the first few pieces of a recorded game
+        let pieces = try!(cli::number_flag(args, "--pieces", 0usize));
+        let preview = try!(cli::number_flag(args, "--preview", 10usize));
+        let game = try!(Replay::load(&PathBuf::from(path))).play_until(pieces);
+        let queue = queue.unwrap_or_else(|| queue_of(&game, preview));
+        return report(game.board(), hold.or(game.held()), &queue, height, limit);
+    }
+    println!("usage: testris pc --board FILE [--queue PIECES] [--hold PIECE] [--height N] \
+              [--max N]");
+    println!("       testris pc --fumen DIAGRAM [--queue PIECES] [--hold PIECE] [--height N] \
+              [--max N]");
+    println!("       testris pc --replay FILE [--pieces N] [--preview N] [--height N] [--max N]");
+    Ok(())
+}
+
+fn report(board: &GameBoard,
+          hold: Option<Shape>,
+          queue: &[Shape],
+          height: usize,
+          limit: Option<usize>)
+          -> Result<()> {
+    let solutions = solve(board, hold, queue, height, limit);
+    println!("{} perfect clears within {} lines", solutions.len(), height);
+    for (i, solution) in solutions.iter().enumerate() {
+        let pieces: Vec<String> = solution.iter()
+            .map(|step| if step.hold {
+                format!("hold {}", step.shape().letter())
+            } else {
+                step.shape().letter().to_string()
+            })
+            .collect();
+        println!("\n{:>3}. {}", i + 1, pieces.join(", "));
+        for row in draw(board, solution, height) {
+            println!("     {}", row);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::Game;
+    use input::Command;
+    use puzzle::Puzzle;
+    use rules::Ruleset;
+    use tetronimo::Shape;
+
+    /// The bottom two rows full except for a 4x2 hole on the left.
+    fn hole() -> &'static str {
+        "....######\n....######\n"
+    }
+
+    #[test]
+    fn fill_a_hole() {
+        let board = GameBoard::parse(hole(), 20).unwrap();
+        // Left O first or right O first
+        let solutions = solve(&board, None, &[Shape::O, Shape::O], 2, None);
+        assert_eq!(2, solutions.len());
+        for solution in &solutions {
+            assert_eq!(vec!["OOOO######".to_string(), "OOOO######".to_string()],
+                       draw(&board, solution, 2));
+        }
+        // The first I clears the bottom row, the second one the next
+        let solutions = solve(&board, None, &[Shape::I, Shape::I], 2, None);
+        assert_eq!(1, solutions.len());
+        assert_eq!(vec!["IIII######".to_string(), "IIII######".to_string()],
+                   draw(&board, &solutions[0], 2));
+        assert!(solve(&board, None, &[Shape::T, Shape::O], 2, None).is_empty());
+        assert!(solve(&board, None, &[Shape::O], 2, None).is_empty());
+        assert_eq!(1, solve(&board, None, &[Shape::O, Shape::O], 2, Some(1)).len());
+    }
+
+    #[test]
+    fn hold_skips_a_piece() {
+        let board = GameBoard::parse(hole(), 20).unwrap();
+        let solutions = solve(&board, None, &[Shape::T, Shape::O, Shape::O], 2, None);
+        assert_eq!(2, solutions.len());
+        for solution in &solutions {
+            assert_eq!(2, solution.len());
+            assert!(solution[0].hold);
+            assert!(solution.iter().all(|s| s.shape() == Shape::O));
+        }
+        assert!(!solve(&board, Some(Shape::O), &[Shape::T, Shape::O], 2, None).is_empty());
+        // The held piece can go last
+        assert!(!solve(&board, Some(Shape::O), &[Shape::O], 2, None).is_empty());
+    }
+
+    #[test]
+    fn spins_under_an_overhang() {
+        // The block at the top left keeps a T from dropping straight in
+        let board = GameBoard::parse("#....#####\n...#######\n#.########\n", 20).unwrap();
+        let solutions = solve(&board, None, &[Shape::T, Shape::I], 3, None);
+        // A T-spin double first, or the I first by holding the T
+        assert_eq!(2, solutions.len());
+        let spin = solutions.iter().find(|s| !s[0].hold).unwrap();
+        assert_eq!(Shape::T, spin[0].shape());
+        let path = &spin[0].placement.path;
+        let last_move = path[path.len() - 2];
+        assert!(last_move == Command::RotateClockwise ||
+                last_move == Command::RotateCounterClockwise);
+        assert_eq!(vec!["#IIII#####".to_string(),
+                        "TTT#######".to_string(),
+                        "#T########".to_string()],
+                   draw(&board, spin, 3));
+    }
+
+    #[test]
+    fn games_are_solved_from_their_queue() {
+        let puzzle = Puzzle::parse(&format!("goal perfect-clear\nqueue OO\n{}", hole())).unwrap();
+        let mut game = Game::puzzle(&puzzle, Ruleset::guideline()).unwrap();
+        game.on_update(0f64);
+        assert_eq!(2, solve_game(&game, 1, 2, None).len());
+        assert!(solve_game(&game, 0, 2, None).is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use piston_window::*;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::canvas::Quad;
+use super::error::{Error, ParseError};
+use super::font;
+use super::game::{self, Game};
+use super::mode::Outcome;
+use super::rules::Ruleset;
+use super::score::Award;
+use super::setup::Setup;
+use super::spin::SpinKind;
+use super::tetronimo::Shape;
+use super::Result;
+
+/// Puzzle files in a pack end with this.
+const EXTENSION: &'static str = "puzzle";
+/// Puzzle boards sit at the bottom of a field this tall.
+const HEIGHT: usize = 20;
+const MARGIN: f64 = 20.0;
+/// Puzzles listed at once, the list scrolls to keep the selection in view.
+const VISIBLE: usize = 20;
+
+/// What a puzzle asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    /// Clear at least this many lines in all.
+    Lines(usize),
+    /// Clear lines and leave nothing on the board.
+    PerfectClear,
+    /// Clear three lines at once with a T-spin.
+    TSpinTriple,
+    /// Place every piece without topping out.
+    Survive,
+}
+
+impl Goal {
+    /// Reads a goal as written in puzzle files, like `lines 4`,
+    /// `perfect-clear`, `tspin-triple` or `survive`.
+    pub fn parse(text: &str) -> Option<Goal> {
+        let mut words = text.split_whitespace();
+        let goal = match words.next() {
+            Some("lines") => {
+                match words.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(lines) if lines > 0 => Goal::Lines(lines),
+                    _ => return None,
+                }
+            }
+            Some("perfect-clear") => Goal::PerfectClear,
+            Some("tspin-triple") => Goal::TSpinTriple,
+            Some("survive") => Goal::Survive,
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(goal),
+        }
+    }
+
+    /// What the player is asked to do, for the screen.
+    pub fn describe(&self) -> String {
+        match *self {
+            Goal::Lines(1) => "Clear 1 line".to_string(),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::PerfectClear => "Perfect Clear".to_string(),
+            Goal::TSpinTriple => "T-Spin Triple".to_string(),
+            Goal::Survive => "Place every piece".to_string(),
+        }
+    }
+
+    /// Whether the lock that earned `award` reaches the goal, with
+    /// `lines` cleared so far and `pieces_left` saying if anything is
+    /// still to be played.
+    pub fn is_met(&self, award: &Award, lines: usize, pieces_left: bool) -> bool {
+        match *self {
+            Goal::Lines(goal) => lines >= goal,
+            Goal::PerfectClear => award.perfect_clear,
+            Goal::TSpinTriple => {
+                award.clear.lines == 3 &&
+                award.clear.spin.map_or(false, |s| s.shape == Shape::T && s.kind == SpinKind::Full)
+            }
+            Goal::Survive => !pieces_left,
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Goal::Lines(lines) => write!(f, "lines {}", lines),
+            Goal::PerfectClear => write!(f, "perfect-clear"),
+            Goal::TSpinTriple => write!(f, "tspin-triple"),
+            Goal::Survive => write!(f, "survive"),
+        }
+    }
+}
+
+/// A challenge kept in a plain text file: a `name` and a `goal`, then
+/// the pieces and board as a `Setup` reads them.
+///
+/// ```text
+/// name Two in the well
+/// goal lines 2
+/// queue II
+/// hold L
+/// ....######
+/// ....######
+/// ```
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    pub setup: Setup,
+}
+
+impl Puzzle {
+    pub fn parse(text: &str) -> Result<Puzzle> {
+        let (setup, headers) = try!(Setup::parse_with_headers(text, HEIGHT));
+        let mut name = String::new();
+        let mut goal = None;
+        for header in headers {
+            let bad = |message: String| Error::BadPuzzle(ParseError::at(header.line, 1, message));
+            match header.key {
+                "name" => name = header.value.to_string(),
+                "goal" => {
+                    goal = Some(try!(Goal::parse(header.value)
+                        .ok_or_else(|| bad(format!("{:?} is not a goal", header.value)))))
+                }
+                key => return Err(bad(format!("unknown header {:?}", key))),
+            }
+        }
+        let goal = try!(goal.ok_or_else(|| Error::BadPuzzle(ParseError::new("missing goal"))));
+        if setup.queue.is_empty() && setup.hold.is_none() {
+            return Err(Error::BadPuzzle(ParseError::new("no pieces to play")));
+        }
+        Ok(Puzzle {
+            name: name,
+            goal: goal,
+            setup: setup,
+        })
+    }
+
+    /// Reads a puzzle file, named after the file unless it says otherwise.
+    pub fn load(path: &Path) -> Result<Puzzle> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| Error::from(e).in_file(path)));
+        let mut puzzle = try!(Puzzle::parse(&text).map_err(|e| e.in_file(path)));
+        if puzzle.name.is_empty() {
+            puzzle.name = path.file_stem()
+                .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+        }
+        Ok(puzzle)
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.name.is_empty() {
+            try!(writeln!(f, "name {}", self.name));
+        }
+        try!(writeln!(f, "goal {}", self.goal));
+        write!(f, "{}", self.setup)
+    }
+}
+
+/// Puzzles played one after another.
+#[derive(Debug, Clone)]
+pub struct Pack {
+    pub puzzles: Vec<Puzzle>,
+}
+
+impl Pack {
+    /// Every `.puzzle` file in a directory in order of file name, or a
+    /// single puzzle file.
+    pub fn load(path: &Path) -> Result<Pack> {
+        if !path.is_dir() {
+            return Ok(Pack { puzzles: vec![try!(Puzzle::load(path))] });
+        }
+        let mut paths = Vec::new();
+        for entry in try!(fs::read_dir(path).map_err(|e| Error::from(e).in_file(path))) {
+            let file = try!(entry).path();
+            if file.extension().map_or(false, |e| e == EXTENSION) {
+                paths.push(file);
+            }
+        }
+        if paths.is_empty() {
+            let message = format!("no .{} files", EXTENSION);
+            return Err(Error::BadPuzzle(ParseError::new(message)).in_file(path));
+        }
+        paths.sort();
+        let puzzles = try!(paths.iter().map(|p| Puzzle::load(p)).collect());
+        Ok(Pack { puzzles: puzzles })
+    }
+}
+
+/// A list of a pack's puzzles to pick from and play, remembering which
+/// were solved. Enter plays the selected puzzle, and once it is over
+/// goes on to the next one. R starts over and Backspace goes back to
+/// the list.
+pub struct Browser {
+    pack: Pack,
+    ruleset: Ruleset,
+    selected: usize,
+    solved: Vec<bool>,
+    playing: Option<Game>,
+    size: [u32; 2],
+}
+
+impl Browser {
+    /// Fails if the pack is empty or any of its puzzles can not be played.
+    pub fn new(pack: Pack, ruleset: Ruleset) -> Result<Browser> {
+        if pack.puzzles.is_empty() {
+            return Err(Error::BadPuzzle(ParseError::new("no puzzles in the pack")));
+        }
+        let mut size = [0, 0];
+        for puzzle in &pack.puzzles {
+            let fits = try!(Game::puzzle(puzzle, ruleset)).window_size();
+            size = [size[0].max(fits[0]), size[1].max(fits[1])];
+        }
+        Ok(Browser {
+            solved: vec![false; pack.puzzles.len()],
+            pack: pack,
+            ruleset: ruleset,
+            selected: 0,
+            playing: None,
+            size: size,
+        })
+    }
+
+    /// Room for the largest of the puzzles.
+    pub fn window_size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn is_solved(&self, i: usize) -> bool {
+        self.solved[i]
+    }
+
+    /// The puzzle being played, `None` while the list is shown.
+    pub fn playing(&self) -> Option<&Game> {
+        self.playing.as_ref()
+    }
+
+    /// Starts the `i`th puzzle from the beginning.
+    pub fn play(&mut self, i: usize) {
+        self.selected = i;
+        let puzzle = &self.pack.puzzles[i];
+        // Every puzzle was started once by `new`
+        let mut game = Game::puzzle(puzzle, self.ruleset).unwrap();
+        game.set_leaderboard(vec![puzzle.goal.describe(),
+                                  String::new(),
+                                  "R to try again".to_string(),
+                                  "Enter for the next one".to_string(),
+                                  "Backspace for the list".to_string()]);
+        self.playing = Some(game);
+    }
+
+    /// The next puzzle after the one just played, or the list after
+    /// the last one.
+    pub fn next(&mut self) {
+        if self.selected + 1 < self.pack.puzzles.len() {
+            let next = self.selected + 1;
+            self.play(next);
+        } else {
+            self.playing = None;
+        }
+    }
+
+    pub fn on_input(&mut self, input: &Input) {
+        let key = match *input {
+            Input::Press(Button::Keyboard(key)) => Some(key),
+            _ => None,
+        };
+        let over = self.playing.as_ref().map(|game| game.is_over());
+        let selected = self.selected;
+        match (over, key) {
+            (None, Some(Key::Up)) => self.selected = selected.saturating_sub(1),
+            (None, Some(Key::Down)) => {
+                self.selected = (selected + 1).min(self.pack.puzzles.len() - 1)
+            }
+            (None, Some(Key::Return)) => self.play(selected),
+            (Some(_), Some(Key::Backspace)) => self.playing = None,
+            (Some(_), Some(Key::R)) => self.play(selected),
+            (Some(true), Some(Key::Return)) => self.next(),
+            _ => {
+                if let Some(ref mut game) = self.playing {
+                    game.on_input(input);
+                }
+            }
+        }
+    }
+
+    pub fn on_update(&mut self, dt: f64) {
+        if let Some(ref mut game) = self.playing {
+            game.on_update(dt);
+            if game.outcome() == Some(Outcome::Completed) {
+                self.solved[self.selected] = true;
+            }
+        }
+    }
+
+    pub fn on_render<G>(&self, g: &mut G, view: math::Matrix2d)
+        where G: Graphics
+    {
+        for quad in self.quads() {
+            rectangle(quad.color, quad.area, view, g);
+        }
+    }
+
+    /// The puzzle being played, or else the list.
+    pub fn quads(&self) -> Vec<Quad> {
+        if let Some(ref game) = self.playing {
+            return game.quads();
+        }
+        let mut quads = Vec::new();
+        let line_height = (font::GLYPH_HEIGHT + 3) as f64 * game::TEXT_SCALE;
+        game::text_quads(&mut quads, MARGIN, MARGIN, "Puzzles");
+        let first = (self.selected + 1).saturating_sub(VISIBLE);
+        for (i, puzzle) in self.pack.puzzles.iter().enumerate().skip(first).take(VISIBLE) {
+            let y = MARGIN + (i - first + 2) as f64 * line_height;
+            if i == self.selected {
+                quads.push(Quad {
+                    color: [0f32, 0f32, 0f32, 0.3f32],
+                    area: [MARGIN / 2f64,
+                           y - line_height / 4f64,
+                           self.size[0] as f64 - MARGIN,
+                           line_height],
+                });
+            }
+            let text = format!("{} {}. {}: {}{}",
+                               if i == self.selected { ">" } else { " " },
+                               i + 1,
+                               puzzle.name,
+                               puzzle.goal.describe(),
+                               if self.solved[i] { " - solved" } else { "" });
+            game::text_quads(&mut quads, MARGIN, y, &text);
+        }
+        let bottom = self.size[1] as f64 - MARGIN - line_height;
+        game::text_quads(&mut quads, MARGIN, bottom, "Up and Down to choose, Enter to play");
+        quads
+    }
+}
+
+/// `testris puzzles PATH` checks a puzzle file or a directory of them
+/// and lists what each one asks for. `testris --puzzles PATH` plays them.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let path = match args.first() {
+        Some(path) => PathBuf::from(path),
+        None => {
+            println!("usage: testris puzzles PATH");
+            return Ok(());
+        }
+    };
+    let pack = try!(Pack::load(&path));
+    for (i, puzzle) in pack.puzzles.iter().enumerate() {
+        let queue: String = puzzle.setup.queue.iter().map(|s| s.letter()).collect();
+        let hold = puzzle.setup.hold.map_or(String::new(), |s| format!(", hold {}", s.letter()));
+        println!("{:>3}. {}: {}, queue {}{}",
+                 i + 1,
+                 puzzle.name,
+                 puzzle.goal.describe(),
+                 queue,
+                 hold);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piston_window::{Button, Input, Key};
+    use error::{Error, ParseError};
+    use game::Game;
+    use mode::Outcome;
+    use movegen;
+    use rules::Ruleset;
+    use score::Award;
+    use spin::{LineClear, Spin, SpinKind};
+    use tetronimo::Shape;
+
+    const WELL: &'static str = "; two in the well\nname Well\ngoal lines 2\nqueue II\nhold L\n\
+                                ....######\n....######\n";
+
+    /// Locks the active piece where it covers `cells`.
+    fn place(game: &mut Game, cells: &[(i32, i32)]) {
+        game.on_update(0f64);
+        let ruleset = game.ruleset();
+        let spins = ruleset.spin_detection;
+        let placement = movegen::placements(game.board(), game.active_piece(), spins)
+            .into_iter()
+            .find(|p| {
+                let mut covered: Vec<(i32, i32)> = p.cells().iter().map(|c| (c.x, c.y)).collect();
+                covered.sort();
+                covered == cells
+            })
+            .unwrap();
+        game.place(&placement.piece, placement.kick).unwrap();
+    }
+
+    fn play(text: &str) -> Game {
+        Game::puzzle(&Puzzle::parse(text).unwrap(), Ruleset::guideline()).unwrap()
+    }
+
+    #[test]
+    fn puzzle_files_round_trip() {
+        let puzzle = Puzzle::parse(WELL).unwrap();
+        assert_eq!("Well", puzzle.name);
+        assert_eq!(Goal::Lines(2), puzzle.goal);
+        assert_eq!(vec![Shape::I, Shape::I], puzzle.setup.queue);
+        assert_eq!(Some(Shape::L), puzzle.setup.hold);
+        assert_eq!(WELL.splitn(2, '\n').nth(1), Some(puzzle.to_string().as_str()));
+
+        for goal in &[Goal::Lines(4), Goal::PerfectClear, Goal::TSpinTriple, Goal::Survive] {
+            assert_eq!(Some(*goal), Goal::parse(&goal.to_string()));
+        }
+        assert_eq!(None, Goal::parse("lines 0"));
+        assert_eq!(None, Goal::parse("survive 10"));
+
+        match Puzzle::parse("queue T\ngoal win\n") {
+            Err(Error::BadPuzzle(ParseError { position: Some((2, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match Puzzle::parse("goal survive\nqueue T\nauthor me\n....\n") {
+            Err(Error::BadPuzzle(ParseError { position: Some((3, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(Puzzle::parse("queue T\n....\n").is_err());
+        assert!(Puzzle::parse("goal survive\n....\n").is_err());
+    }
+
+    #[test]
+    fn goals_decide_how_puzzles_end() {
+        let mut game = play(WELL);
+        place(&mut game, &[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert!(!game.is_over());
+        assert_eq!(Some(Shape::I), game.preview(0).map(|p| p.shape()));
+        assert!(game.preview(1).is_none());
+        // Solved with the held piece to spare
+        place(&mut game, &[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(Some(Outcome::Completed), game.outcome());
+
+        // The O can not finish the well, and the held L is played last
+        let mut game = play("goal lines 2\nqueue O\nhold L\n....######\n....######\n");
+        place(&mut game, &[(0, 0), (0, 1), (1, 0), (1, 1)]);
+        assert!(!game.is_over());
+        assert_eq!(Shape::L, {
+            game.on_update(0f64);
+            game.active_piece().shape()
+        });
+        assert!(game.held().is_none());
+        place(&mut game, &[(2, 0), (2, 1), (2, 2), (3, 0)]);
+        assert_eq!(Some(Outcome::Failed), game.outcome());
+
+        let mut game = play("goal survive\nqueue O\n..........\n");
+        place(&mut game, &[(0, 0), (0, 1), (1, 0), (1, 1)]);
+        assert_eq!(Some(Outcome::Completed), game.outcome());
+    }
+
+    #[test]
+    fn browsing_goes_on_to_the_next_puzzle() {
+        let puzzles = vec![Puzzle::parse(WELL).unwrap(),
+                           Puzzle::parse("goal survive\nqueue O\n..........\n").unwrap()];
+        let mut browser = Browser::new(Pack { puzzles: puzzles }, Ruleset::guideline()).unwrap();
+        let press = |key| Input::Press(Button::Keyboard(key));
+        browser.on_input(&press(Key::Down));
+        browser.on_input(&press(Key::Return));
+        assert_eq!(1, browser.selected());
+        place(browser.playing.as_mut().unwrap(), &[(0, 0), (0, 1), (1, 0), (1, 1)]);
+        browser.on_update(0f64);
+        assert!(browser.is_solved(1));
+        assert!(!browser.is_solved(0));
+
+        // After the last puzzle comes the list
+        browser.on_input(&press(Key::Return));
+        assert!(browser.playing().is_none());
+        assert!(!browser.quads().is_empty());
+
+        assert!(Browser::new(Pack { puzzles: Vec::new() }, Ruleset::guideline()).is_err());
+    }
+
+    #[test]
+    fn triples_need_a_t_spin() {
+        let mut award = Award {
+            clear: LineClear {
+                lines: 3,
+                spin: None,
+            },
+            points: 0,
+            attack: 0,
+            back_to_back: false,
+            combo: 0,
+            perfect_clear: false,
+        };
+        assert!(!Goal::TSpinTriple.is_met(&award, 3, true));
+        award.clear.spin = Some(Spin {
+            shape: Shape::T,
+            kind: SpinKind::Full,
+        });
+        assert!(Goal::TSpinTriple.is_met(&award, 3, true));
+        assert!(!Goal::PerfectClear.is_met(&award, 3, true));
+        assert!(!Goal::Survive.is_met(&award, 3, true));
+    }
+}
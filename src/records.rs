@@ -0,0 +1,369 @@
+use rustc_serialize::json::{Json, ToJson};
+use toml;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::cli;
+use super::error::{Error, ParseError};
+use super::mode::Mode;
+use super::Result;
+
+/// Format version of the records file.
+pub const VERSION: i64 = 1;
+/// How many records are kept for every mode and ruleset.
+pub const TOP_N: usize = 10;
+
+/// Where records and replays are kept, following the XDG base directory spec.
+pub fn data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(ref dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("testris")),
+        _ => env::home_dir().map(|home| home.join(".local/share/testris")),
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("records.toml"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub mode: String,
+    pub ruleset: String,
+    pub score: u64,
+    pub lines: usize,
+    /// Seconds played
+    pub time: f64,
+    pub date: String,
+    pub seed: u32,
+    /// Path to the replay file, empty if it could not be saved.
+    pub replay: String,
+}
+
+impl Record {
+    fn same_board(&self, mode: &str, ruleset: &str) -> bool {
+        self.mode == mode && self.ruleset == ruleset
+    }
+
+    /// Sprints rank by time, everything else by score.
+    fn rank_order(a: &Record, b: &Record) -> Ordering {
+        match Mode::by_name(&a.mode) {
+            Some(Mode::Sprint) => a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal),
+            _ => b.score.cmp(&a.score),
+        }
+    }
+
+    /// One line for the leaderboard, like ` 1. 62.51s 10-18`.
+    pub fn summary(&self, rank: usize) -> String {
+        let result = match Mode::by_name(&self.mode) {
+            Some(Mode::Sprint) => format!("{:.2}s", self.time),
+            _ => self.score.to_string(),
+        };
+        let day: String = self.date.chars().skip(5).take(5).collect();
+        format!("{:>2}. {} {}", rank + 1, result, day)
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        let mut table = BTreeMap::new();
+        table.insert("mode".to_string(), toml::Value::String(self.mode.clone()));
+        table.insert("ruleset".to_string(),
+                     toml::Value::String(self.ruleset.clone()));
+        table.insert("score".to_string(), toml::Value::Integer(self.score as i64));
+        table.insert("lines".to_string(), toml::Value::Integer(self.lines as i64));
+        table.insert("time".to_string(), toml::Value::Float(self.time));
+        table.insert("date".to_string(), toml::Value::String(self.date.clone()));
+        table.insert("seed".to_string(), toml::Value::Integer(self.seed as i64));
+        table.insert("replay".to_string(), toml::Value::String(self.replay.clone()));
+        toml::Value::Table(table)
+    }
+
+    fn from_toml(value: &toml::Value) -> Result<Record> {
+        let table = try!(value.as_table()
+            .ok_or_else(|| Error::BadRecords(ParseError::new("record is not a table"))));
+        let missing = |key: &str| {
+            Error::BadRecords(ParseError::new(format!("record is missing {:?}", key)))
+        };
+        let text = |key: &str| {
+            table.get(key)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| missing(key))
+        };
+        let number = |key: &str| {
+            table.get(key)
+                .and_then(|v| v.as_integer())
+                .and_then(|i| if i >= 0 { Some(i) } else { None })
+                .ok_or_else(|| missing(key))
+        };
+        Ok(Record {
+            mode: try!(text("mode")),
+            ruleset: try!(text("ruleset")),
+            score: try!(number("score")) as u64,
+            lines: try!(number("lines")) as usize,
+            time: try!(table.get("time").and_then(|v| v.as_float()).ok_or_else(|| missing("time"))),
+            date: try!(text("date")),
+            seed: try!(number("seed")) as u32,
+            replay: try!(text("replay")),
+        })
+    }
+}
+
+impl ToJson for Record {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("mode".to_string(), self.mode.to_json());
+        object.insert("ruleset".to_string(), self.ruleset.to_json());
+        object.insert("score".to_string(), self.score.to_json());
+        object.insert("lines".to_string(), self.lines.to_json());
+        object.insert("time".to_string(), self.time.to_json());
+        object.insert("date".to_string(), self.date.to_json());
+        object.insert("seed".to_string(), self.seed.to_json());
+        object.insert("replay".to_string(), self.replay.to_json());
+        Json::Object(object)
+    }
+}
+
+/// The best games for every mode and ruleset, kept in a TOML file.
+#[derive(Debug)]
+pub struct RecordStore {
+    path: PathBuf,
+    records: Vec<Record>,
+}
+
+impl RecordStore {
+    /// Loads the records file, a missing file is an empty store.
+    pub fn open(path: &Path) -> Result<RecordStore> {
+        let mut text = String::new();
+        let records = match File::open(path).and_then(|mut file| file.read_to_string(&mut text)) {
+            Ok(_) => try!(RecordStore::parse(&text).map_err(|e| e.in_file(path))),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(Error::from(e).in_file(path)),
+        };
+        Ok(RecordStore {
+            path: path.to_path_buf(),
+            records: records,
+        })
+    }
+
+    fn parse(text: &str) -> Result<Vec<Record>> {
+        let mut parser = toml::Parser::new(text);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(Error::BadRecords(ParseError::at(line + 1, col + 1, e.desc.clone())));
+            }
+        };
+
+        match table.get("version").and_then(|v| v.as_integer()) {
+            Some(VERSION) => {}
+            Some(version) => return Err(Error::UnsupportedRecords(version)),
+            None => return Err(Error::BadRecords(ParseError::new("missing version"))),
+        }
+        let records = match table.get("records") {
+            Some(value) => {
+                try!(value.as_slice()
+                    .ok_or_else(|| Error::BadRecords(ParseError::new("records is not a list"))))
+            }
+            None => return Ok(Vec::new()),
+        };
+        records.iter().map(Record::from_toml).collect()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut root = BTreeMap::new();
+        root.insert("version".to_string(), toml::Value::Integer(VERSION));
+        root.insert("records".to_string(),
+                    toml::Value::Array(self.records.iter().map(|r| r.to_toml()).collect()));
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .and_then(|_| File::create(&self.path))
+            .and_then(|mut file| write!(file, "{}", toml::Value::Table(root)))
+            .map_err(|e| Error::from(e).in_file(&self.path))
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Adds a finished game, returning its rank if it made the top N.
+    pub fn insert(&mut self, record: Record) -> Option<usize> {
+        let (mode, ruleset) = (record.mode.clone(), record.ruleset.clone());
+        let mut board: Vec<Record> = self.records
+            .iter()
+            .filter(|r| r.same_board(&mode, &ruleset))
+            .cloned()
+            .collect();
+        self.records.retain(|r| !r.same_board(&mode, &ruleset));
+
+        board.sort_by(Record::rank_order);
+        // Ties go to whoever got there first
+        let rank = board.iter()
+            .position(|r| Record::rank_order(&record, r) == Ordering::Less)
+            .unwrap_or(board.len());
+        board.insert(rank, record);
+        board.truncate(TOP_N);
+        self.records.extend(board);
+        if rank < TOP_N { Some(rank) } else { None }
+    }
+
+    /// Best records first.
+    pub fn leaderboard(&self, mode: &str, ruleset: &str) -> Vec<&Record> {
+        let mut board: Vec<&Record> = self.records
+            .iter()
+            .filter(|r| r.same_board(mode, ruleset))
+            .collect();
+        board.sort_by(|a, b| Record::rank_order(a, b));
+        board
+    }
+}
+
+/// `testris records list|export [--file records.toml] ...`
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let path = match cli::flag_value(args, "--file") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            try!(default_path().ok_or(Error::Io(io::Error::new(io::ErrorKind::NotFound,
+                                                                "no home directory"))))
+        }
+    };
+    let store = try!(RecordStore::open(&path));
+
+    match args.get(0).map(|s| s.as_str()) {
+        Some("list") => {
+            let modes = match cli::flag_value(args, "--mode") {
+                Some(mode) => vec![mode.to_string()],
+                None => vec!["marathon".to_string(), "sprint".to_string()],
+            };
+            let rules = match cli::flag_value(args, "--rules") {
+                Some(rules) => vec![rules.to_string()],
+                None => vec!["guideline".to_string(), "allspin".to_string()],
+            };
+            for mode in &modes {
+                for ruleset in &rules {
+                    let board = store.leaderboard(mode, ruleset);
+                    if board.is_empty() {
+                        continue;
+                    }
+                    println!("{} / {}", mode, ruleset);
+                    for (rank, record) in board.iter().enumerate() {
+                        println!("{:>2}. score {:>8}  lines {:>4}  time {:>8.2}s  {}  seed {}  {}",
+                                 rank + 1,
+                                 record.score,
+                                 record.lines,
+                                 record.time,
+                                 record.date,
+                                 record.seed,
+                                 record.replay);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some("export") => {
+            let json = Json::Array(store.records().iter().map(|r| r.to_json()).collect());
+            match cli::flag_value(args, "--output") {
+                Some(output) => {
+                    try!(File::create(output)
+                        .and_then(|mut file| writeln!(file, "{}", json.pretty()))
+                        .map_err(|e| Error::from(e).in_file(Path::new(output))));
+                }
+                None => println!("{}", json.pretty()),
+            }
+            Ok(())
+        }
+        _ => {
+            println!("usage: testris records list [--mode MODE] [--rules RULES] [--file FILE]");
+            println!("       testris records export [--output FILE] [--file FILE]");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{Error, ParseError};
+
+    fn record(mode: &str, score: u64, time: f64) -> Record {
+        Record {
+            mode: mode.to_string(),
+            ruleset: "guideline".to_string(),
+            score: score,
+            lines: 40,
+            time: time,
+            date: "2026-10-18 12:00".to_string(),
+            seed: 7,
+            replay: String::new(),
+        }
+    }
+
+    fn store() -> RecordStore {
+        RecordStore {
+            path: PathBuf::from("records.toml"),
+            records: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn marathon_ranks_by_score() {
+        let mut store = store();
+        assert_eq!(Some(0), store.insert(record("marathon", 100, 10f64)));
+        assert_eq!(Some(0), store.insert(record("marathon", 300, 10f64)));
+        assert_eq!(Some(1), store.insert(record("marathon", 200, 10f64)));
+        let scores: Vec<u64> = store.leaderboard("marathon", "guideline")
+            .iter()
+            .map(|r| r.score)
+            .collect();
+        assert_eq!(vec![300, 200, 100], scores);
+
+        // The same score again ranks below the one already there
+        assert_eq!(Some(2), store.insert(record("marathon", 200, 10f64)));
+    }
+
+    #[test]
+    fn sprint_ranks_by_time_and_keeps_top_n() {
+        let mut store = store();
+        for i in 0..TOP_N {
+            store.insert(record("sprint", 0, 60f64 + i as f64));
+        }
+        assert_eq!(None, store.insert(record("sprint", 0, 100f64)));
+        assert_eq!(Some(0), store.insert(record("sprint", 0, 30f64)));
+        assert_eq!(TOP_N, store.leaderboard("sprint", "guideline").len());
+        assert_eq!(" 1. 30.00s 10-18", store.leaderboard("sprint", "guideline")[0].summary(0));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut store = store();
+        store.insert(record("sprint", 0, 61.5f64));
+        store.insert(record("marathon", 1234, 200f64));
+        let mut root = BTreeMap::new();
+        root.insert("version".to_string(), toml::Value::Integer(VERSION));
+        root.insert("records".to_string(),
+                    toml::Value::Array(store.records.iter().map(|r| r.to_toml()).collect()));
+        let text = toml::Value::Table(root).to_string();
+        assert_eq!(store.records, RecordStore::parse(&text).unwrap());
+    }
+
+    #[test]
+    fn corrupted_files_are_errors() {
+        match RecordStore::parse("version = 1\n[[records]]\nmode = 3\n") {
+            Err(Error::BadRecords(_)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match RecordStore::parse("version = 0\n") {
+            Err(Error::UnsupportedRecords(0)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match RecordStore::parse("version = 1\nrecords = ]\n") {
+            Err(Error::BadRecords(ParseError { position: Some((2, _)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
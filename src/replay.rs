@@ -0,0 +1,373 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use super::board::Dimensions;
+use super::error::{Error, ParseError};
+use super::game::Game;
+use super::input::Command;
+use super::mode::Mode;
+use super::rules::Ruleset;
+use super::tetronimo::{Tetromino, TetronimoState};
+use super::transform::{Orientation, Point, RotationDirection};
+use super::Result;
+
+/// Raised whenever an event is added, so older builds refuse replays
+/// they can not read. Version 2 added placements and 3 garbage.
+pub const VERSION: u32 = 3;
+const HEADER: &'static str = "testris-replay";
+
+/// Everything the engine consumes, in the order it happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayEvent {
+    Update(f64),
+    Press(Command),
+    Release(Command),
+    /// A piece locked by `Game::place`, with its origin, orientation and kick.
+    Place(Point, Orientation, Option<usize>),
+    /// Garbage lines sent by an opponent.
+    Garbage(usize),
+}
+
+fn orientation_name(orientation: Orientation) -> &'static str {
+    match orientation {
+        Orientation::North => "North",
+        Orientation::East => "East",
+        Orientation::South => "South",
+        Orientation::West => "West",
+    }
+}
+
+fn orientation_by_name(name: &str) -> Option<Orientation> {
+    [Orientation::North, Orientation::East, Orientation::South, Orientation::West]
+        .iter()
+        .find(|o| orientation_name(**o) == name)
+        .cloned()
+}
+
+/// A seed plus the input log is enough to re-simulate a whole game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u32,
+    pub mode: Mode,
+    pub ruleset: Ruleset,
+    pub dimensions: Dimensions,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn new(seed: u32, mode: Mode, ruleset: Ruleset, dimensions: Dimensions) -> Self {
+        Replay {
+            seed: seed,
+            mode: mode,
+            ruleset: ruleset,
+            dimensions: dimensions,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, event: ReplayEvent) {
+        self.events.push(event);
+    }
+
+    /// A new game set up exactly like the recorded one.
+    pub fn new_game(&self) -> Game {
+        // Dimensions are checked when a replay is recorded or parsed
+        Game::with_dimensions(self.mode, self.ruleset, self.seed, self.dimensions).unwrap()
+    }
+
+    /// Feeds a recorded event back into a game.
+    pub fn apply(game: &mut Game, event: &ReplayEvent) {
+        match *event {
+            ReplayEvent::Update(dt) => game.on_update(dt),
+            ReplayEvent::Press(command) => game.on_press(command),
+            ReplayEvent::Release(command) => game.on_release(command),
+            ReplayEvent::Place(origin, orientation, kick) => {
+                let mut piece = Tetromino::new_shape(game.active_piece().shape());
+                piece.put(origin);
+                while piece.orientation() != orientation {
+                    piece.rotate(&RotationDirection::Clockwise);
+                }
+                if let Err(e) = game.place(&piece, kick) {
+                    error!("Could not replay placement: {}", e);
+                }
+            }
+            ReplayEvent::Garbage(lines) => game.receive_garbage(lines),
+        }
+    }
+
+    /// Plays events from `next` up to and including the next recorded
+    /// update, for watching at the recorded pace. Returns where to go
+    /// on from.
+    pub fn play_frame(&self, game: &mut Game, mut next: usize) -> usize {
+        while let Some(event) = self.events.get(next) {
+            next += 1;
+            Replay::apply(game, event);
+            if let ReplayEvent::Update(_) = *event {
+                break;
+            }
+        }
+        next
+    }
+
+    /// Re-simulates the whole recording.
+    pub fn play(&self) -> Game {
+        let mut game = self.new_game();
+        for event in &self.events {
+            Replay::apply(&mut game, event);
+        }
+        game
+    }
+
+    /// Re-simulates the recording up to the point where `pieces` pieces
+    /// have been locked and the next one has spawned.
+    pub fn play_until(&self, pieces: usize) -> Game {
+        let mut game = self.new_game();
+        for event in &self.events {
+            let spawned = game.active_piece().state == TetronimoState::Falling;
+            if game.statistics().pieces() >= pieces && spawned {
+                break;
+            }
+            Replay::apply(&mut game, event);
+        }
+        game
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        File::create(path)
+            .and_then(|mut file| write!(file, "{}", self))
+            .map_err(|e| Error::from(e).in_file(path))
+    }
+
+    pub fn load(path: &Path) -> Result<Replay> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| Error::from(e).in_file(path)));
+        Replay::parse(&text).map_err(|e| e.in_file(path))
+    }
+
+    pub fn parse(text: &str) -> Result<Replay> {
+        let mut lines = text.lines().enumerate();
+        let header = lines.next().map(|(_, line)| line).unwrap_or("");
+        let mut words = header.split_whitespace();
+        if words.next() != Some(HEADER) {
+            return Err(Error::BadReplay(ParseError::at(1, 1, "not a testris replay")));
+        }
+        match words.next().and_then(|v| v.parse::<u32>().ok()) {
+            // Every older event is still understood
+            Some(version) if version >= 1 && version <= VERSION => {}
+            Some(version) => return Err(Error::UnsupportedReplay(version)),
+            None => return Err(Error::BadReplay(ParseError::at(1, 1, "missing version"))),
+        }
+
+        let mut seed = None;
+        let mut mode = None;
+        let mut ruleset = None;
+        let mut dimensions = Dimensions::standard();
+        let mut events = Vec::new();
+        for (number, line) in lines {
+            let bad = || Error::BadReplay(ParseError::at(number + 1, 1, format!("{:?}", line)));
+            let mut words = line.split_whitespace();
+            let key = words.next();
+            let value = words.next().unwrap_or("");
+            match key {
+                Some("seed") => seed = Some(try!(value.parse::<u32>().map_err(|_| bad()))),
+                Some("mode") => mode = Some(try!(Mode::by_name(value).ok_or_else(&bad))),
+                Some("rules") => ruleset = Some(try!(Ruleset::by_name(value).ok_or_else(&bad))),
+                Some("board") => {
+                    let mut numbers = line.split_whitespace()
+                        .skip(1)
+                        .map(|n| n.parse::<usize>().map_err(|_| bad()));
+                    let mut next = || numbers.next().unwrap_or_else(|| Err(bad()));
+                    dimensions = Dimensions {
+                        width: try!(next()),
+                        height: try!(next()),
+                        hidden: try!(next()),
+                    };
+                    try!(dimensions.check());
+                }
+                Some("u") => {
+                    let dt = try!(value.parse::<f64>().map_err(|_| bad()));
+                    events.push(ReplayEvent::Update(dt));
+                }
+                Some("p") => {
+                    let command = try!(Command::by_name(value).ok_or_else(&bad));
+                    events.push(ReplayEvent::Press(command));
+                }
+                Some("r") => {
+                    let command = try!(Command::by_name(value).ok_or_else(&bad));
+                    events.push(ReplayEvent::Release(command));
+                }
+                Some("l") => {
+                    let fields: Vec<&str> = line.split_whitespace().skip(1).collect();
+                    if fields.len() != 4 {
+                        return Err(bad());
+                    }
+                    let x = try!(fields[0].parse::<i32>().map_err(|_| bad()));
+                    let y = try!(fields[1].parse::<i32>().map_err(|_| bad()));
+                    let orientation = try!(orientation_by_name(fields[2]).ok_or_else(&bad));
+                    let kick = match fields[3] {
+                        "-" => None,
+                        kick => Some(try!(kick.parse::<usize>().map_err(|_| bad()))),
+                    };
+                    events.push(ReplayEvent::Place(Point::new(x, y), orientation, kick));
+                }
+                Some("g") => {
+                    let lines = try!(value.parse::<usize>().map_err(|_| bad()));
+                    events.push(ReplayEvent::Garbage(lines));
+                }
+                None => {}
+                Some(_) => return Err(bad()),
+            }
+        }
+
+        match (seed, mode, ruleset) {
+            (Some(seed), Some(mode), Some(ruleset)) => {
+                Ok(Replay {
+                    seed: seed,
+                    mode: mode,
+                    ruleset: ruleset,
+                    dimensions: dimensions,
+                    events: events,
+                })
+            }
+            _ => Err(Error::BadReplay(ParseError::new("missing seed, mode or rules"))),
+        }
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{} {}", HEADER, VERSION));
+        try!(writeln!(f, "seed {}", self.seed));
+        try!(writeln!(f, "mode {}", self.mode.name()));
+        try!(writeln!(f, "rules {}", self.ruleset.name));
+        try!(writeln!(f,
+                      "board {} {} {}",
+                      self.dimensions.width,
+                      self.dimensions.height,
+                      self.dimensions.hidden));
+        for event in &self.events {
+            try!(match *event {
+                ReplayEvent::Update(dt) => writeln!(f, "u {}", dt),
+                ReplayEvent::Press(command) => writeln!(f, "p {}", command.name()),
+                ReplayEvent::Release(command) => writeln!(f, "r {}", command.name()),
+                ReplayEvent::Place(origin, orientation, kick) => {
+                    let kick = kick.map(|k| k.to_string()).unwrap_or("-".to_string());
+                    writeln!(f,
+                             "l {} {} {} {}",
+                             origin.x,
+                             origin.y,
+                             orientation_name(orientation),
+                             kick)
+                }
+                ReplayEvent::Garbage(lines) => writeln!(f, "g {}", lines),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::Dimensions;
+    use error::{Error, ParseError};
+    use input::Command;
+    use mode::Mode;
+    use rules::Ruleset;
+    use transform::{Orientation, Point};
+
+    #[test]
+    fn text_round_trip() {
+        let dimensions = Dimensions {
+            width: 6,
+            height: 12,
+            hidden: 3,
+        };
+        let mut replay = Replay::new(42, Mode::Sprint, Ruleset::all_spin(), dimensions);
+        replay.record(ReplayEvent::Update(1f64 / 60f64));
+        replay.record(ReplayEvent::Press(Command::SlideLeft));
+        replay.record(ReplayEvent::Release(Command::SlideLeft));
+        replay.record(ReplayEvent::Place(Point::new(3, -1), Orientation::West, Some(4)));
+        replay.record(ReplayEvent::Place(Point::new(0, 2), Orientation::North, None));
+        replay.record(ReplayEvent::Garbage(3));
+        let text = replay.to_string();
+        assert_eq!(replay, Replay::parse(&text).unwrap());
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        match Replay::parse("testris-replay 0\nseed 1\nmode sprint\nrules guideline\n") {
+            Err(Error::UnsupportedReplay(0)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match Replay::parse(&format!("testris-replay {}\nseed 1\nmode sprint\n", VERSION + 1)) {
+            Err(Error::UnsupportedReplay(version)) => assert_eq!(VERSION + 1, version),
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(Replay::parse("testris-replay 1\nseed 1\nmode sprint\nrules guideline\n").is_ok());
+        assert!(Replay::parse("").is_err());
+        match Replay::parse("testris-replay 1\nseed x\nmode sprint\nrules guideline\n") {
+            Err(Error::BadReplay(ParseError { position: Some((2, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        let narrow = "testris-replay 1\nseed 1\nmode sprint\nrules guideline\nboard 2 20 2\n";
+        match Replay::parse(narrow) {
+            Err(Error::InvalidDimensions { width: 2, .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn play_back_matches_original() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 7);
+        let inputs = [Command::SlideLeft, Command::RotateClockwise, Command::Lock, Command::Hold];
+        for i in 0..600 {
+            if i % 15 == 0 {
+                let command = inputs[(i / 15) % inputs.len()];
+                game.on_press(command);
+                game.on_update(0.016);
+                game.on_release(command);
+            }
+            game.on_update(0.016);
+        }
+        let copy = game.replay().play();
+        assert_eq!(format!("{:?}", game.board()), format!("{:?}", copy.board()));
+        assert_eq!(format!("{:?}", game.statistics()),
+                   format!("{:?}", copy.statistics()));
+    }
+
+    #[test]
+    fn placed_pieces_play_back() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 5);
+        for _ in 0..40 {
+            assert!(::bot::play_piece(&mut game, &::bot::Weights::default(), 0));
+        }
+        let text = game.replay().to_string();
+        assert!(text.contains("\nl "));
+        let copy = Replay::parse(&text).unwrap().play();
+        assert_eq!(format!("{:?}", game.board()), format!("{:?}", copy.board()));
+        assert_eq!(game.score(), copy.score());
+    }
+
+    #[test]
+    fn pieces_spawn_inside_any_field() {
+        for &(width, height) in &[(4, 4), (10, 20), (40, 100)] {
+            let dimensions = Dimensions {
+                width: width,
+                height: height,
+                hidden: 2,
+            };
+            for seed in 0..5 {
+                let mut game =
+                    Game::with_dimensions(Mode::Marathon, Ruleset::guideline(), seed, dimensions)
+                        .unwrap();
+                game.on_update(0.016);
+                assert!(!game.is_over());
+            }
+        }
+    }
+}
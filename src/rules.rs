@@ -0,0 +1,40 @@
+use super::spin::SpinDetection;
+
+/// The knobs a game mode can turn on the core engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ruleset {
+    pub name: &'static str,
+    pub spin_detection: SpinDetection,
+}
+
+impl Ruleset {
+    /// Standard rules, only T pieces can spin.
+    pub fn guideline() -> Self {
+        Ruleset {
+            name: "guideline",
+            spin_detection: SpinDetection::TSpin,
+        }
+    }
+
+    /// Every piece can spin when it is stuck after rotating.
+    pub fn all_spin() -> Self {
+        Ruleset {
+            name: "allspin",
+            spin_detection: SpinDetection::Immobile,
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        [Ruleset::guideline(), Ruleset::all_spin()]
+            .iter()
+            .find(|r| r.name == name)
+            .cloned()
+    }
+}
+
+#[test]
+fn lookup_by_name() {
+    assert_eq!(Some(Ruleset::guideline()), Ruleset::by_name("guideline"));
+    assert_eq!(Some(Ruleset::all_spin()), Ruleset::by_name("allspin"));
+    assert_eq!(None, Ruleset::by_name("sticky"));
+}
@@ -0,0 +1,225 @@
+use super::spin::{LineClear, SpinKind};
+
+/// Everything that happened when a single piece locked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Award {
+    pub clear: LineClear,
+    pub points: u64,
+    /// Garbage lines this lock would send to an opponent.
+    pub attack: usize,
+    pub back_to_back: bool,
+    pub combo: usize,
+    pub perfect_clear: bool,
+}
+
+impl Award {
+    /// Text to flash on screen, empty if nothing noteworthy happened.
+    pub fn callouts(&self) -> Vec<String> {
+        let mut callouts = Vec::new();
+        if self.back_to_back {
+            callouts.push("Back-to-Back".to_string());
+        }
+        let name = self.clear.name();
+        if !name.is_empty() {
+            callouts.push(name);
+        }
+        if self.combo > 0 {
+            callouts.push(format!("Combo {}", self.combo));
+        }
+        if self.perfect_clear {
+            callouts.push("Perfect Clear".to_string());
+        }
+        callouts
+    }
+}
+
+/// Keeps score, along with the back-to-back and combo chains.
+#[derive(Debug)]
+pub struct Scorer {
+    score: u64,
+    lines: usize,
+    combo: Option<usize>,
+    back_to_back: bool,
+}
+
+impl Scorer {
+    pub fn new() -> Self {
+        Scorer {
+            score: 0,
+            lines: 0,
+            combo: None,
+            back_to_back: false,
+        }
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Must be called every time a piece locks, even if nothing cleared,
+    /// so that combos can be broken.
+    pub fn award(&mut self, clear: LineClear, perfect_clear: bool) -> Award {
+        let back_to_back = clear.is_difficult() && self.back_to_back;
+        if clear.lines > 0 {
+            self.back_to_back = clear.is_difficult();
+            self.combo = Some(self.combo.map(|c| c + 1).unwrap_or(0));
+        } else {
+            self.combo = None;
+        }
+        let combo = self.combo.unwrap_or(0);
+
+        let mut points = base_points(&clear);
+        if back_to_back {
+            points = points * 3 / 2;
+        }
+        points += 50 * combo as u64;
+        if perfect_clear {
+            points += perfect_clear_points(clear.lines);
+        }
+
+        let mut attack = base_attack(&clear) + combo_attack(combo);
+        if back_to_back {
+            attack += 1;
+        }
+        if perfect_clear {
+            attack += 10;
+        }
+
+        self.score += points;
+        self.lines += clear.lines;
+        Award {
+            clear: clear,
+            points: points,
+            attack: attack,
+            back_to_back: back_to_back,
+            combo: combo,
+            perfect_clear: perfect_clear,
+        }
+    }
+}
+
+fn base_points(clear: &LineClear) -> u64 {
+    match (clear.spin.map(|s| s.kind), clear.lines) {
+        (None, 0) => 0,
+        (None, 1) => 100,
+        (None, 2) => 300,
+        (None, 3) => 500,
+        (None, _) => 800,
+        (Some(SpinKind::Mini), 0) => 100,
+        (Some(SpinKind::Mini), 1) => 200,
+        (Some(SpinKind::Mini), _) => 400,
+        (Some(SpinKind::Full), 0) => 400,
+        (Some(SpinKind::Full), 1) => 800,
+        (Some(SpinKind::Full), 2) => 1200,
+        (Some(SpinKind::Full), _) => 1600,
+    }
+}
+
+fn base_attack(clear: &LineClear) -> usize {
+    match (clear.spin.map(|s| s.kind), clear.lines) {
+        (_, 0) => 0,
+        (None, 1) => 0,
+        (None, 2) => 1,
+        (None, 3) => 2,
+        (None, _) => 4,
+        (Some(SpinKind::Mini), n) => n - 1,
+        (Some(SpinKind::Full), n) => 2 * n,
+    }
+}
+
+fn combo_attack(combo: usize) -> usize {
+    match combo {
+        0 => 0,
+        1...2 => 1,
+        3...4 => 2,
+        5...6 => 3,
+        7...9 => 4,
+        _ => 5,
+    }
+}
+
+fn perfect_clear_points(lines: usize) -> u64 {
+    match lines {
+        0 => 0,
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        _ => 2000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin::{LineClear, Spin, SpinKind};
+    use tetronimo::Shape;
+
+    fn lines(n: usize) -> LineClear {
+        LineClear {
+            lines: n,
+            spin: None,
+        }
+    }
+
+    fn t_spin(n: usize) -> LineClear {
+        LineClear {
+            lines: n,
+            spin: Some(Spin {
+                shape: Shape::T,
+                kind: SpinKind::Full,
+            }),
+        }
+    }
+
+    #[test]
+    fn back_to_back_tetris() {
+        let mut scorer = Scorer::new();
+        assert!(!scorer.award(lines(4), false).back_to_back);
+        let award = scorer.award(lines(4), false);
+        assert!(award.back_to_back);
+        assert_eq!(1200 + 50, award.points);
+        assert_eq!(4 + 1 + 1, award.attack);
+    }
+
+    #[test]
+    fn t_spin_attack() {
+        let mut scorer = Scorer::new();
+        assert_eq!(4, scorer.award(t_spin(2), false).attack);
+        // A single sends nothing on its own, but it continues the combo
+        assert_eq!(1, scorer.award(lines(1), false).attack);
+    }
+
+    #[test]
+    fn back_to_back_survives_placements_but_not_easy_clears() {
+        let mut scorer = Scorer::new();
+        scorer.award(t_spin(2), false);
+        scorer.award(lines(0), false);
+        assert!(scorer.award(lines(4), false).back_to_back);
+        scorer.award(lines(1), false);
+        assert!(!scorer.award(t_spin(1), false).back_to_back);
+    }
+
+    #[test]
+    fn combo_counts_consecutive_clears() {
+        let mut scorer = Scorer::new();
+        assert_eq!(0, scorer.award(lines(1), false).combo);
+        assert_eq!(1, scorer.award(lines(1), false).combo);
+        assert_eq!(2, scorer.award(lines(2), false).combo);
+        assert_eq!(0, scorer.award(lines(0), false).combo);
+        assert_eq!(0, scorer.award(lines(1), false).combo);
+        assert_eq!(5, scorer.lines());
+    }
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let mut scorer = Scorer::new();
+        let award = scorer.award(lines(2), true);
+        assert_eq!(300 + 1200, award.points);
+        assert_eq!(vec!["Double".to_string(), "Perfect Clear".to_string()],
+                   award.callouts());
+    }
+}
@@ -0,0 +1,31 @@
+use rand::{SeedableRng, XorShiftRng};
+
+/// What a generator is drawing for. Each gets numbers of its own from
+/// the same seed, so adding garbage to a game leaves its pieces alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Pieces,
+    Garbage,
+    Tuning,
+}
+
+/// The same seed and stream always draw the same numbers.
+pub fn seeded_rng(seed: u32, stream: Stream) -> XorShiftRng {
+    // XorShift needs a seed that is not all zeros, digits of pi and e
+    // fill in the rest
+    let rest = match stream {
+        Stream::Pieces => [0x9e3779b9, 0x243f6a88, 0xb7e15162],
+        Stream::Garbage => [0x85a308d3, 0x13198a2e, 0x03707344],
+        Stream::Tuning => [0xa4093822, 0x299f31d0, 0x082efa98],
+    };
+    XorShiftRng::from_seed([seed, rest[0], rest[1], rest[2]])
+}
+
+#[test]
+fn streams_differ() {
+    use rand::Rng;
+    let draw = |stream| seeded_rng(0, stream).gen_iter::<u32>().take(4).collect::<Vec<_>>();
+    assert_eq!(draw(Stream::Pieces), draw(Stream::Pieces));
+    assert!(draw(Stream::Pieces) != draw(Stream::Garbage));
+    assert!(draw(Stream::Garbage) != draw(Stream::Tuning));
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::board::GameBoard;
+use super::error::{Error, ParseError};
+use super::tetronimo::Shape;
+use super::Result;
+
+/// A board and the pieces to play on it, as kept in plain text files:
+/// optional `queue` and `hold` lines, then the board as
+/// `GameBoard::parse` reads it.
+///
+/// ```text
+/// ; a T-spin double
+/// queue TI
+/// hold O
+/// #....#####
+/// ...#######
+/// #.########
+/// ```
+#[derive(Debug, Clone)]
+pub struct Setup {
+    pub board: GameBoard,
+    /// Pieces in the order they come.
+    pub queue: Vec<Shape>,
+    pub hold: Option<Shape>,
+}
+
+/// A `key value` line among the rows of a board, `line` counting from
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header<'a> {
+    pub line: usize,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+impl Setup {
+    /// Reads a setup whose board sits at the bottom of a field `height`
+    /// rows tall.
+    pub fn parse(text: &str, height: usize) -> Result<Setup> {
+        let (setup, headers) = try!(Setup::parse_with_headers(text, height));
+        match headers.first() {
+            Some(header) => {
+                let message = format!("unknown header {:?}", header.key);
+                Err(Error::BadBoard(ParseError::at(header.line, 1, message)))
+            }
+            None => Ok(setup),
+        }
+    }
+
+    /// Like `parse`, but hands back the headers other than `queue` and
+    /// `hold`, for files that say more than a setup does.
+    pub fn parse_with_headers(text: &str, height: usize) -> Result<(Setup, Vec<Header>)> {
+        let mut queue = Vec::new();
+        let mut hold = None;
+        let mut others = Vec::new();
+        // Header lines are blanked out so board errors keep their line
+        let mut rows = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            let header = match trimmed.find(char::is_whitespace) {
+                Some(i) if !trimmed.starts_with(';') => {
+                    Header {
+                        line: number + 1,
+                        key: &trimmed[..i],
+                        value: trimmed[i..].trim(),
+                    }
+                }
+                _ => {
+                    rows.push(line);
+                    continue;
+                }
+            };
+            rows.push("");
+            let bad = || Error::BadBoard(ParseError::at(number + 1, 1, format!("{:?}", line)));
+            let pieces: String = header.value.split_whitespace().collect();
+            match header.key {
+                "queue" => queue = try!(parse_queue(&pieces).map_err(|_| bad())),
+                "hold" => {
+                    let pieces = try!(parse_queue(&pieces).map_err(|_| bad()));
+                    if pieces.len() > 1 {
+                        return Err(bad());
+                    }
+                    hold = pieces.first().cloned();
+                }
+                _ => others.push(header),
+            }
+        }
+        let setup = Setup {
+            board: try!(GameBoard::parse(&rows.join("\n"), height)),
+            queue: queue,
+            hold: hold,
+        };
+        Ok((setup, others))
+    }
+
+    pub fn load(path: &Path, height: usize) -> Result<Setup> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|e| Error::from(e).in_file(path)));
+        Setup::parse(&text, height).map_err(|e| e.in_file(path))
+    }
+}
+
+impl fmt::Display for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.queue.is_empty() {
+            let queue: String = self.queue.iter().map(|s| s.letter()).collect();
+            try!(writeln!(f, "queue {}", queue));
+        }
+        if let Some(hold) = self.hold {
+            try!(writeln!(f, "hold {}", hold.letter()));
+        }
+        write!(f, "{}", self.board)
+    }
+}
+
+/// Pieces written as their letters, like `TIOS`.
+pub fn parse_queue(text: &str) -> Result<Vec<Shape>> {
+    text.chars()
+        .map(|c| {
+            Shape::by_letter(c).ok_or_else(|| {
+                Error::BadBoard(ParseError::new(format!("{:?} is not a piece", c)))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{Error, ParseError};
+    use tetronimo::Shape;
+
+    #[test]
+    fn headers_give_the_pieces() {
+        let text = "; a T-spin double\nqueue TI\nhold O\n#....#####\n...#######\n#.########\n";
+        let setup = Setup::parse(text, 20).unwrap();
+        assert_eq!(vec![Shape::T, Shape::I], setup.queue);
+        assert_eq!(Some(Shape::O), setup.hold);
+        assert_eq!(6, setup.board.bits().row(2).count_ones());
+        assert_eq!(text.splitn(2, '\n').nth(1), Some(setup.to_string().as_str()));
+
+        match Setup::parse("queue TX\n....\n", 20) {
+            Err(Error::BadBoard(ParseError { position: Some((1, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        // Rows keep their line numbers with headers above them
+        match Setup::parse("hold T\n....\n...\n", 20) {
+            Err(Error::BadBoard(ParseError { position: Some((3, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        // Anything else is for the caller to make sense of
+        let (_, headers) = Setup::parse_with_headers("name Two  words\nqueue T\n....\n", 20)
+            .unwrap();
+        assert_eq!(vec![Header { line: 1, key: "name", value: "Two  words" }], headers);
+        match Setup::parse("....\nname Well\n", 20) {
+            Err(Error::BadBoard(ParseError { position: Some((2, 1)), .. })) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
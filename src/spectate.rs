@@ -0,0 +1,418 @@
+use rustc_serialize::json::{Json, ToJson};
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use super::bitboard::BitBoard;
+use super::error::{Error, ParseError};
+use super::game::{Game, GameEvent};
+use super::mode::Outcome;
+use super::tetronimo::{Shape, Tetromino};
+use super::transform::Point;
+use super::Result;
+
+/// Upcoming pieces listed in a snapshot.
+const QUEUE: usize = 5;
+
+fn object(event: &str, fields: Vec<(&str, Json)>) -> Json {
+    let mut object = BTreeMap::new();
+    object.insert("event".to_string(), event.to_json());
+    for (key, value) in fields {
+        object.insert(key.to_string(), value);
+    }
+    Json::Object(object)
+}
+
+fn shape_json(shape: Shape) -> Json {
+    shape.letter().to_string().to_json()
+}
+
+fn cells_json(piece: &Tetromino) -> Json {
+    Json::Array(piece.cells().iter().map(|p| vec![p.x, p.y].to_json()).collect())
+}
+
+/// `{"piece": .., "cells": ..}`, the falling piece in a snapshot.
+fn piece_json(piece: &Tetromino) -> Json {
+    let mut object = BTreeMap::new();
+    object.insert("piece".to_string(), shape_json(piece.shape()));
+    object.insert("cells".to_string(), cells_json(piece));
+    Json::Object(object)
+}
+
+fn outcome_name(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::ToppedOut => "topped out",
+        Outcome::Completed => "completed",
+        Outcome::Won => "won",
+        Outcome::Failed => "failed",
+    }
+}
+
+/// One line of the stream for a game event.
+pub fn event_json(event: &GameEvent) -> Json {
+    match *event {
+        GameEvent::Spawn(ref piece) => {
+            object("spawn",
+                   vec![("piece", shape_json(piece.shape())), ("cells", cells_json(piece))])
+        }
+        GameEvent::Hold(shape) => object("hold", vec![("piece", shape_json(shape))]),
+        GameEvent::Lock(ref piece) => {
+            object("lock",
+                   vec![("piece", shape_json(piece.shape())), ("cells", cells_json(piece))])
+        }
+        GameEvent::Clear(ref award) => {
+            object("clear",
+                   vec![("lines", award.clear.lines.to_json()),
+                        ("callouts", award.callouts().to_json()),
+                        ("points", award.points.to_json()),
+                        ("attack", award.attack.to_json())])
+        }
+        GameEvent::Garbage(lines, hole) => {
+            object("garbage", vec![("lines", lines.to_json()), ("hole", hole.to_json())])
+        }
+        GameEvent::Over(outcome) => {
+            object("over", vec![("outcome", outcome_name(outcome).to_json())])
+        }
+    }
+}
+
+fn score_json(game: &Game) -> Json {
+    object("score",
+           vec![("score", game.score().to_json()), ("lines", game.lines().to_json())])
+}
+
+/// The whole state of a game, the first line every spectator gets.
+pub fn snapshot(game: &Game) -> Json {
+    let board = game.board().bits();
+    let dimensions = game.board().dimensions();
+    let active = if game.is_over() {
+        Json::Null
+    } else {
+        piece_json(game.active_piece())
+    };
+    let rows: Vec<u64> = (0..board.height()).map(|y| board.row(y)).collect();
+    object("snapshot",
+           vec![("seed", game.replay().seed.to_json()),
+                ("mode", game.mode().name().to_json()),
+                ("rules", game.ruleset().name.to_json()),
+                ("width", dimensions.width.to_json()),
+                ("height", dimensions.height.to_json()),
+                ("hidden", dimensions.hidden.to_json()),
+                ("rows", rows.to_json()),
+                ("active", active),
+                ("hold", game.held().map(shape_json).unwrap_or(Json::Null)),
+                ("queue",
+                 Json::Array((0..QUEUE)
+                     .filter_map(|i| game.preview(i))
+                     .map(|p| shape_json(p.shape()))
+                     .collect())),
+                ("score", game.score().to_json()),
+                ("lines", game.lines().to_json()),
+                ("outcome",
+                 game.outcome().map(|o| outcome_name(o).to_json()).unwrap_or(Json::Null))])
+}
+
+/// Streams a game to anyone who connects, one JSON object per line.
+/// Each spectator has a thread of its own, so a slow one never holds
+/// up the game.
+pub struct Broadcaster {
+    listener: TcpListener,
+    spectators: Vec<Sender<String>>,
+    score: u64,
+}
+
+impl Broadcaster {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Broadcaster> {
+        let listener = try!(TcpListener::bind(address));
+        try!(listener.set_nonblocking(true));
+        Ok(Broadcaster {
+            listener: listener,
+            spectators: Vec::new(),
+            score: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(try!(self.listener.local_addr()))
+    }
+
+    /// Sends what happened since the last call, then lets in new
+    /// spectators with a snapshot. Call after every update.
+    pub fn publish(&mut self, game: &mut Game) {
+        let mut lines: Vec<String> = game.take_events()
+            .iter()
+            .map(|event| event_json(event).to_string())
+            .collect();
+        if game.score() != self.score {
+            self.score = game.score();
+            lines.push(score_json(game).to_string());
+        }
+        // Spectators whose thread has stopped have gone away
+        self.spectators
+            .retain(|spectator| lines.iter().all(|line| spectator.send(line.clone()).is_ok()));
+
+        while let Ok((stream, address)) = self.listener.accept() {
+            info!("Spectator joined from {}", address);
+            let spectator = watch(stream);
+            if spectator.send(snapshot(game).to_string()).is_ok() {
+                self.spectators.push(spectator);
+            }
+        }
+    }
+}
+
+fn watch(mut stream: TcpStream) -> Sender<String> {
+    let (sender, lines) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        if stream.set_nonblocking(false).is_err() {
+            return;
+        }
+        for line in lines {
+            if writeln!(stream, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+    sender
+}
+
+/// Rebuilds a game from the stream, as far as it can be seen.
+pub struct Spectator {
+    board: BitBoard,
+    hidden: usize,
+    active: Option<(Shape, Vec<Point>)>,
+    hold: Option<Shape>,
+    score: u64,
+    lines: usize,
+    outcome: Option<String>,
+}
+
+impl Spectator {
+    pub fn new() -> Self {
+        Spectator {
+            board: BitBoard::new(10, 22),
+            hidden: 2,
+            active: None,
+            hold: None,
+            score: 0,
+            lines: 0,
+            outcome: None,
+        }
+    }
+
+    pub fn board(&self) -> &BitBoard {
+        &self.board
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    /// Takes in one line of the stream, returning the kind of event.
+    pub fn apply(&mut self, line: &str) -> Result<String> {
+        let bad = |message: &str| {
+            Error::BadMessage(ParseError::new(format!("{}: {:?}", message, line)))
+        };
+        let json = try!(Json::from_str(line).map_err(|_| bad("not JSON")));
+        let number = |key: &str| {
+            json.find(key).and_then(|n| n.as_u64()).map(|n| n as usize).ok_or_else(|| bad(key))
+        };
+        let event = try!(json.find("event")
+            .and_then(|e| e.as_string())
+            .map(|e| e.to_string())
+            .ok_or_else(|| bad("no event")));
+        match event.as_str() {
+            "snapshot" => {
+                let width = try!(number("width"));
+                let hidden = try!(number("hidden"));
+                if width == 0 || width > 64 {
+                    return Err(bad("width"));
+                }
+                self.board = BitBoard::new(width, try!(number("height")) + hidden);
+                self.hidden = hidden;
+                let rows = json.find("rows")
+                    .and_then(|r| r.as_array())
+                    .cloned()
+                    .unwrap_or(Vec::new());
+                let height = self.board.height();
+                for (y, row) in rows.iter().enumerate().take(height) {
+                    let row = row.as_u64().unwrap_or(0);
+                    for x in (0..width).filter(|x| row & (1 << x) != 0) {
+                        self.board.set(x, y);
+                    }
+                }
+                self.active = json.find("active").and_then(piece_of);
+                self.hold = shape_of(json.find("hold"));
+                self.score = try!(number("score")) as u64;
+                self.lines = try!(number("lines"));
+                self.outcome = outcome_of(&json);
+            }
+            "spawn" => self.active = Some(try!(piece_of(&json).ok_or_else(|| bad("piece")))),
+            "hold" => {
+                self.hold = Some(try!(shape_of(json.find("piece")).ok_or_else(|| bad("piece"))))
+            }
+            "lock" => {
+                let (_, cells) = try!(piece_of(&json).ok_or_else(|| bad("piece")));
+                for p in cells {
+                    if !self.board.is_occupied(p.x, p.y) {
+                        self.board.set(p.x as usize, p.y as usize);
+                    }
+                }
+                self.board.clear_full_rows();
+                self.active = None;
+            }
+            "garbage" => {
+                let hole = try!(number("hole"));
+                if hole >= self.board.width() {
+                    return Err(bad("hole"));
+                }
+                self.board.add_garbage(try!(number("lines")), hole);
+            }
+            "score" => {
+                self.score = try!(number("score")) as u64;
+                self.lines = try!(number("lines"));
+            }
+            "over" => self.outcome = outcome_of(&json),
+            _ => {}
+        }
+        Ok(event)
+    }
+
+    /// The visible part of the board, top row first, with the falling
+    /// piece drawn as its letter, followed by the score.
+    pub fn draw(&self) -> String {
+        let mut text = String::new();
+        for y in (0..self.board.height() - self.hidden).rev() {
+            for x in 0..self.board.width() {
+                let point = Point::new(x as i32, y as i32);
+                let active = match self.active {
+                    Some((shape, ref cells)) if cells.contains(&point) => Some(shape.letter()),
+                    _ => None,
+                };
+                text.push(match active {
+                    Some(letter) => letter,
+                    None if self.board.is_occupied(point.x, point.y) => '#',
+                    None => '.',
+                });
+            }
+            text.push('\n');
+        }
+        text.push_str(&format!("score {}  lines {}  hold {}",
+                               self.score,
+                               self.lines,
+                               self.hold.map(|s| s.letter()).unwrap_or('-')));
+        if let Some(ref outcome) = self.outcome {
+            text.push_str(&format!("  {}", outcome));
+        }
+        text
+    }
+}
+
+fn shape_of(value: Option<&Json>) -> Option<Shape> {
+    value.and_then(|v| v.as_string()).and_then(|s| s.chars().next()).and_then(Shape::by_letter)
+}
+
+fn outcome_of(value: &Json) -> Option<String> {
+    value.find("outcome").and_then(|o| o.as_string()).map(|o| o.to_string())
+}
+
+/// The shape and cells of a `{"piece": .., "cells": ..}` object.
+fn piece_of(value: &Json) -> Option<(Shape, Vec<Point>)> {
+    let cells: Vec<Point> = match value.find("cells").and_then(|c| c.as_array()) {
+        Some(cells) => {
+            cells.iter()
+                .filter_map(|c| c.as_array())
+                .filter_map(|c| {
+                    match (c.get(0).and_then(|x| x.as_i64()), c.get(1).and_then(|y| y.as_i64())) {
+                        (Some(x), Some(y)) => Some(Point::new(x as i32, y as i32)),
+                        _ => None,
+                    }
+                })
+                .collect()
+        }
+        None => return None,
+    };
+    shape_of(value.find("piece")).map(|shape| (shape, cells))
+}
+
+/// `testris watch`, prints a streamed game after every lock.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let address = match args.first() {
+        Some(address) if !address.starts_with("--") => address.clone(),
+        _ => {
+            println!("usage: testris watch HOST:PORT");
+            return Ok(());
+        }
+    };
+    let stream = try!(TcpStream::connect(address.as_str()));
+    let mut spectator = Spectator::new();
+    for line in BufReader::new(stream).lines() {
+        let line = try!(line);
+        match spectator.apply(&line) {
+            Ok(ref event) if event == "snapshot" || event == "lock" || event == "over" => {
+                println!("{}\n", spectator.draw());
+            }
+            Ok(_) => {}
+            Err(e) => error!("Skipped a line: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bot::{self, Weights};
+    use game::Game;
+    use mode::Mode;
+    use rules::Ruleset;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+
+    #[test]
+    fn spectators_rebuild_the_game() {
+        let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 8);
+        // Nobody asked for the first spawn
+        assert!(game.take_events().is_empty());
+        game.enable_events();
+        // Joining late, the snapshot catches up on the first pieces
+        for _ in 0..10 {
+            assert!(bot::play_piece(&mut game, &Weights::default(), 0));
+            broadcaster.publish(&mut game);
+        }
+        let stream = TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+        broadcaster.publish(&mut game);
+        for i in 0..30 {
+            if i == 5 {
+                game.receive_garbage(2);
+            }
+            assert!(bot::play_piece(&mut game, &Weights::default(), 0));
+            broadcaster.publish(&mut game);
+        }
+        // Hanging up ends the stream
+        drop(broadcaster);
+
+        let mut spectator = Spectator::new();
+        let mut events = Vec::new();
+        for line in BufReader::new(stream).lines() {
+            events.push(spectator.apply(&line.unwrap()).unwrap());
+        }
+        assert_eq!("snapshot", events[0]);
+        assert!(events.iter().any(|e| e == "garbage"));
+        assert_eq!(30, events.iter().filter(|e| *e == "lock").count());
+        assert_eq!(game.board().bits(), spectator.board());
+        assert_eq!(game.score(), spectator.score());
+        assert!(spectator.draw().starts_with(".........."));
+        // Holes off the board are turned away rather than shifted in
+        assert!(spectator.apply(r#"{"event": "garbage", "lines": 1, "hole": 10}"#).is_err());
+        assert!(spectator.apply(r#"{"event": "garbage", "lines": 1, "hole": 64}"#).is_err());
+        assert_eq!(game.board().bits(), spectator.board());
+        let active = snapshot(&game).find("active").cloned().unwrap();
+        assert!(active.find("event").is_none());
+        assert!(piece_of(&active).is_some());
+    }
+}
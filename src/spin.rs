@@ -0,0 +1,236 @@
+use super::board::GameBoard;
+use super::tetronimo::{Shape, Tetromino};
+use super::transform::{self, Point};
+
+/// How a ruleset decides whether a lock counts as a spin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinDetection {
+    /// Only T pieces spin, judged by the three-corner rule.
+    TSpin,
+    /// T pieces use the three-corner rule, and any piece that
+    /// can not move left, right or up after rotating is a spin.
+    Immobile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinKind {
+    Mini,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spin {
+    pub shape: Shape,
+    pub kind: SpinKind,
+}
+
+/// Reported every time a piece locks and either clears lines or spins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineClear {
+    pub lines: usize,
+    pub spin: Option<Spin>,
+}
+
+impl LineClear {
+    /// Human readable name, like "Tetris" or "T-Spin Mini Single".
+    pub fn name(&self) -> String {
+        let lines = match self.lines {
+            0 => "",
+            1 => "Single",
+            2 => "Double",
+            3 => "Triple",
+            _ => "Tetris",
+        };
+        let spin = match self.spin {
+            Some(Spin { shape, kind: SpinKind::Full }) => format!("{:?}-Spin", shape),
+            Some(Spin { shape, kind: SpinKind::Mini }) => format!("{:?}-Spin Mini", shape),
+            None => String::new(),
+        };
+        match (spin.is_empty(), lines.is_empty()) {
+            (true, _) => lines.to_string(),
+            (false, true) => spin,
+            (false, false) => format!("{} {}", spin, lines),
+        }
+    }
+
+    /// Tetrises and spins that clear lines keep a back-to-back chain going.
+    pub fn is_difficult(&self) -> bool {
+        self.lines >= 4 || (self.lines > 0 && self.spin.is_some())
+    }
+}
+
+/// The final SRS kick test, which always upgrades a mini to a full spin.
+const LAST_KICK: usize = 4;
+
+/// Checks a piece whose last successful move was a rotation using
+/// wall kick number `kick`. The piece must not yet be on the board.
+pub fn detect(board: &GameBoard,
+              piece: &Tetromino,
+              kick: usize,
+              detection: SpinDetection)
+              -> Option<Spin> {
+    let corners = match piece.shape() {
+        Shape::T => three_corner(board, piece, kick),
+        _ => None,
+    };
+    if corners.is_some() {
+        return corners.map(|kind| {
+            Spin {
+                shape: piece.shape(),
+                kind: kind,
+            }
+        });
+    }
+
+    match detection {
+        SpinDetection::TSpin => None,
+        SpinDetection::Immobile => {
+            if is_immobile(board, piece) {
+                Some(Spin {
+                    shape: piece.shape(),
+                    kind: SpinKind::Full,
+                })
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn three_corner(board: &GameBoard, piece: &Tetromino, kick: usize) -> Option<SpinKind> {
+    // Corners around the center of a north facing T, the first two
+    // are on the side the T points towards.
+    let corners = [Point::new(-1, 1), Point::new(1, 1), Point::new(1, -1), Point::new(-1, -1)];
+    let occupied: Vec<bool> = corners.iter()
+        .map(|c| {
+            let offset = transform::transform(c, &piece.orientation());
+            board.is_occupied(&(piece.origin() + offset.to_vector()))
+        })
+        .collect();
+
+    let total = occupied.iter().filter(|o| **o).count();
+    let front = occupied[0..2].iter().filter(|o| **o).count();
+    if total < 3 {
+        None
+    } else if front == 2 || kick == LAST_KICK {
+        Some(SpinKind::Full)
+    } else {
+        Some(SpinKind::Mini)
+    }
+}
+
+fn is_immobile(board: &GameBoard, piece: &Tetromino) -> bool {
+    [Point::new(-1, 0), Point::new(1, 0), Point::new(0, 1)]
+        .iter()
+        .all(|offset| {
+            let mut test_piece = piece.clone();
+            test_piece.translate(offset);
+            !board.check_piece(&test_piece)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::{GameBoard, Pixel};
+    use block::Block;
+    use tetriscolor::Color;
+    use tetronimo::{Shape, Tetromino};
+    use transform::{Point, RotationDirection};
+
+    fn board_with(points: &[(i32, i32)]) -> GameBoard {
+        let mut board = GameBoard::new(3, 3, 0, Pixel::new(0f64, 0f64));
+        let blocks: Vec<Block> = points.iter()
+            .map(|&(x, y)| Block::new(Color::white(), Point::new(x, y)))
+            .collect();
+        board.add_blocks(&blocks);
+        board
+    }
+
+    fn south_t() -> Tetromino {
+        let mut piece = Tetromino::new_shape(Shape::T);
+        piece.rotate(&RotationDirection::Clockwise);
+        piece.rotate(&RotationDirection::Clockwise);
+        piece.put(Point::new(1, 1));
+        piece
+    }
+
+    #[test]
+    fn t_spin_full() {
+        let board = board_with(&[(0, 0), (2, 0), (0, 2)]);
+        let spin = detect(&board, &south_t(), 0, SpinDetection::TSpin);
+        assert_eq!(Some(Spin {
+                       shape: Shape::T,
+                       kind: SpinKind::Full,
+                   }),
+                   spin);
+    }
+
+    #[test]
+    fn t_spin_mini() {
+        let board = board_with(&[(0, 0), (0, 2), (2, 2)]);
+        let spin = detect(&board, &south_t(), 0, SpinDetection::TSpin);
+        assert_eq!(Some(Spin {
+                       shape: Shape::T,
+                       kind: SpinKind::Mini,
+                   }),
+                   spin);
+    }
+
+    #[test]
+    fn last_kick_upgrades_mini() {
+        let board = board_with(&[(0, 0), (0, 2), (2, 2)]);
+        let spin = detect(&board, &south_t(), LAST_KICK, SpinDetection::TSpin);
+        assert_eq!(Some(SpinKind::Full), spin.map(|s| s.kind));
+    }
+
+    #[test]
+    fn two_corners_is_not_a_spin() {
+        let board = board_with(&[(0, 0), (2, 0)]);
+        assert_eq!(None, detect(&board, &south_t(), 0, SpinDetection::Immobile));
+    }
+
+    #[test]
+    fn clear_names() {
+        let t_spin = Some(Spin {
+            shape: Shape::T,
+            kind: SpinKind::Full,
+        });
+        let mini = Some(Spin {
+            shape: Shape::T,
+            kind: SpinKind::Mini,
+        });
+        assert_eq!("Tetris",
+                   LineClear {
+                           lines: 4,
+                           spin: None,
+                       }
+                       .name());
+        assert_eq!("T-Spin Double",
+                   LineClear {
+                           lines: 2,
+                           spin: t_spin,
+                       }
+                       .name());
+        assert_eq!("T-Spin Mini",
+                   LineClear {
+                           lines: 0,
+                           spin: mini,
+                       }
+                       .name());
+    }
+
+    #[test]
+    fn immobile_l() {
+        let board = board_with(&[(0, 1), (1, 1)]);
+        let mut piece = Tetromino::new_shape(Shape::L);
+        piece.put(Point::new(1, 0));
+        assert!(board.check_piece(&piece));
+        assert_eq!(None, detect(&board, &piece, 0, SpinDetection::TSpin));
+        assert_eq!(Some(Spin {
+                       shape: Shape::L,
+                       kind: SpinKind::Full,
+                   }),
+                   detect(&board, &piece, 0, SpinDetection::Immobile));
+    }
+}
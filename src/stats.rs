@@ -0,0 +1,203 @@
+use rustc_serialize::json::{Json, ToJson};
+
+use std::collections::BTreeMap;
+
+use super::score::Award;
+
+/// Running totals for a single game, used for the
+/// side panel and the summary written at game end.
+#[derive(Debug)]
+pub struct Statistics {
+    elapsed: f64,
+    score: u64,
+    pieces: usize,
+    lines: usize,
+    attack: usize,
+    keys: usize,
+    holds: usize,
+    spins: usize,
+    finesse_faults: usize,
+    clears: BTreeMap<String, usize>,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Statistics {
+            elapsed: 0f64,
+            score: 0,
+            pieces: 0,
+            lines: 0,
+            attack: 0,
+            keys: 0,
+            holds: 0,
+            spins: 0,
+            finesse_faults: 0,
+            clears: BTreeMap::new(),
+        }
+    }
+
+    pub fn elapsed(&mut self, dt: f64) {
+        self.elapsed += dt;
+    }
+
+    pub fn on_key(&mut self) {
+        self.keys += 1;
+    }
+
+    pub fn on_hold(&mut self) {
+        self.holds += 1;
+    }
+
+    pub fn on_finesse_fault(&mut self) {
+        self.finesse_faults += 1;
+    }
+
+    pub fn on_lock(&mut self, award: &Award) {
+        self.pieces += 1;
+        self.score += award.points;
+        self.lines += award.clear.lines;
+        self.attack += award.attack;
+        if award.clear.spin.is_some() {
+            self.spins += 1;
+        }
+        let name = award.clear.name();
+        if !name.is_empty() {
+            *self.clears.entry(name).or_insert(0) += 1;
+        }
+    }
+
+    /// Pieces locked so far
+    pub fn pieces(&self) -> usize {
+        self.pieces
+    }
+
+    /// Seconds played so far
+    pub fn time(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Pieces per second
+    pub fn pps(&self) -> f64 {
+        self.per_second(self.pieces)
+    }
+
+    /// Lines per minute
+    pub fn lpm(&self) -> f64 {
+        self.per_second(self.lines) * 60f64
+    }
+
+    /// Attack per minute
+    pub fn apm(&self) -> f64 {
+        self.per_second(self.attack) * 60f64
+    }
+
+    /// Keys per piece
+    pub fn kpp(&self) -> f64 {
+        match self.pieces {
+            0 => 0f64,
+            pieces => self.keys as f64 / pieces as f64,
+        }
+    }
+
+    fn per_second(&self, count: usize) -> f64 {
+        if self.elapsed > 0f64 {
+            count as f64 / self.elapsed
+        } else {
+            0f64
+        }
+    }
+
+    /// Lines of text for the side panel.
+    pub fn panel(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Time {:.1}", self.elapsed),
+            format!("Pieces {}", self.pieces),
+            format!("PPS {:.2}", self.pps()),
+            format!("LPM {:.1}", self.lpm()),
+            format!("APM {:.1}", self.apm()),
+            format!("KPP {:.2}", self.kpp()),
+            format!("Holds {}", self.holds),
+            format!("Spins {}", self.spins),
+            format!("Finesse {}", self.finesse_faults),
+        ];
+        for (name, count) in &self.clears {
+            lines.push(format!("{} {}", name, count));
+        }
+        lines
+    }
+}
+
+impl ToJson for Statistics {
+    fn to_json(&self) -> Json {
+        let mut clears = BTreeMap::new();
+        for (name, count) in &self.clears {
+            clears.insert(name.clone(), count.to_json());
+        }
+
+        let mut summary = BTreeMap::new();
+        summary.insert("time".to_string(), self.elapsed.to_json());
+        summary.insert("score".to_string(), self.score.to_json());
+        summary.insert("pieces".to_string(), self.pieces.to_json());
+        summary.insert("lines".to_string(), self.lines.to_json());
+        summary.insert("attack".to_string(), self.attack.to_json());
+        summary.insert("keys".to_string(), self.keys.to_json());
+        summary.insert("holds".to_string(), self.holds.to_json());
+        summary.insert("spins".to_string(), self.spins.to_json());
+        summary.insert("finesse_faults".to_string(), self.finesse_faults.to_json());
+        summary.insert("pps".to_string(), self.pps().to_json());
+        summary.insert("lpm".to_string(), self.lpm().to_json());
+        summary.insert("apm".to_string(), self.apm().to_json());
+        summary.insert("kpp".to_string(), self.kpp().to_json());
+        summary.insert("clears".to_string(), Json::Object(clears));
+        Json::Object(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json::ToJson;
+    use score::Scorer;
+    use spin::LineClear;
+
+    fn lock(stats: &mut Statistics, scorer: &mut Scorer, lines: usize) {
+        let award = scorer.award(LineClear {
+                                     lines: lines,
+                                     spin: None,
+                                 },
+                                 false);
+        stats.on_lock(&award);
+    }
+
+    #[test]
+    fn rates() {
+        let mut stats = Statistics::new();
+        let mut scorer = Scorer::new();
+        assert_eq!(0f64, stats.pps());
+        assert_eq!(0f64, stats.kpp());
+        for _ in 0..3 {
+            stats.on_key();
+            stats.on_key();
+            lock(&mut stats, &mut scorer, 0);
+        }
+        lock(&mut stats, &mut scorer, 4);
+        stats.elapsed(2f64);
+        assert_eq!(2f64, stats.pps());
+        assert_eq!(120f64, stats.lpm());
+        assert_eq!(120f64, stats.apm());
+        assert_eq!(1.5f64, stats.kpp());
+    }
+
+    #[test]
+    fn clears_by_name() {
+        let mut stats = Statistics::new();
+        let mut scorer = Scorer::new();
+        lock(&mut stats, &mut scorer, 1);
+        lock(&mut stats, &mut scorer, 0);
+        lock(&mut stats, &mut scorer, 1);
+        let json = stats.to_json();
+        let singles = json.find_path(&["clears", "Single"]).and_then(|j| j.as_u64());
+        assert_eq!(Some(2), singles);
+        assert_eq!(Some(3), json.find("pieces").and_then(|j| j.as_u64()));
+    }
+}
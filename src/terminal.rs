@@ -0,0 +1,360 @@
+use libc;
+
+use std::io::{self, Write};
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::bot::Bot;
+use super::env::FRAME;
+use super::game::Game;
+use super::input::Command;
+use super::mode::Outcome;
+use super::replay::Replay;
+use super::spectate::Broadcaster;
+use super::tetriscolor::Color;
+use super::tetronimo::{Shape, Tetromino, TetronimoState};
+use super::Result;
+
+/// How cells are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    /// Two colored spaces per cell, one line per row.
+    Blocks,
+    /// Unicode half blocks, two rows per line, for small terminals.
+    HalfBlocks,
+}
+
+/// A key as read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermKey {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+    /// Ctrl-C, which raw mode delivers as a plain byte.
+    Interrupt,
+}
+
+/// Keys parsed from a chunk of terminal input, arrow keys being escape
+/// sequences.
+pub fn parse_keys(bytes: &[u8]) -> Vec<TermKey> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match (bytes[i], bytes.get(i + 1), bytes.get(i + 2)) {
+            (0x1b, Some(&b'['), Some(&code)) => {
+                i += 2;
+                match code {
+                    b'A' => TermKey::Up,
+                    b'B' => TermKey::Down,
+                    b'C' => TermKey::Right,
+                    b'D' => TermKey::Left,
+                    _ => TermKey::Escape,
+                }
+            }
+            (0x1b, _, _) => TermKey::Escape,
+            (0x03, _, _) => TermKey::Interrupt,
+            (byte, _, _) => TermKey::Char(byte as char),
+        };
+        keys.push(key);
+        i += 1;
+    }
+    keys
+}
+
+/// What a key does, terminals cannot tell when a key is let go so
+/// every key is a tap.
+pub fn command(key: TermKey) -> Option<Command> {
+    match key {
+        TermKey::Left => Some(Command::SlideLeft),
+        TermKey::Right => Some(Command::SlideRight),
+        TermKey::Down => Some(Command::DownFast),
+        TermKey::Up | TermKey::Char('x') => Some(Command::RotateClockwise),
+        TermKey::Char('z') => Some(Command::RotateCounterClockwise),
+        TermKey::Char(' ') => Some(Command::Lock),
+        TermKey::Char('c') => Some(Command::Hold),
+        _ => None,
+    }
+}
+
+fn is_quit(key: &TermKey) -> bool {
+    match *key {
+        TermKey::Escape | TermKey::Interrupt | TermKey::Char('q') => true,
+        _ => false,
+    }
+}
+
+/// Keeps the terminal in raw mode, where keys arrive as they are
+/// pressed and are not echoed, until dropped. Dropping also shows the
+/// cursor again, in case an error left it hidden.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<RawMode> {
+        unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            // Reads return straight away, with or without input
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(RawMode { original: original })
+        }
+    }
+
+    /// Keys pressed since the last call, without waiting.
+    pub fn read_keys(&self) -> Vec<TermKey> {
+        let mut buffer = [0u8; 64];
+        let read = unsafe {
+            libc::read(libc::STDIN_FILENO,
+                       buffer.as_mut_ptr() as *mut libc::c_void,
+                       buffer.len())
+        };
+        if read <= 0 {
+            return Vec::new();
+        }
+        parse_keys(&buffer[..read as usize])
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+        let mut out = io::stdout();
+        let _ = write!(out, "{}\x1b[?25h", RESET);
+        let _ = out.flush();
+    }
+}
+
+/// Colors are blended onto black, terminals have no transparency.
+fn rgb(color: &Color) -> (u8, u8, u8) {
+    let channel = |c: f32| (c * color.alpha * 255f32).max(0f32).min(255f32) as u8;
+    (channel(color.red), channel(color.green), channel(color.blue))
+}
+
+fn background(color: &Color) -> String {
+    let (r, g, b) = rgb(color);
+    format!("\x1b[48;2;{};{};{}m", r, g, b)
+}
+
+fn foreground(color: &Color) -> String {
+    let (r, g, b) = rgb(color);
+    format!("\x1b[38;2;{};{};{}m", r, g, b)
+}
+
+const RESET: &'static str = "\x1b[0m";
+
+/// Colors of the visible field, bottom row first, `None` where empty.
+fn cells(game: &Game) -> Vec<Vec<Option<Color>>> {
+    let board = game.board();
+    let mut cells = vec![vec![None; board.width()]; board.height()];
+    for block in board.blocks() {
+        if board.is_occupied(&block.point) {
+            cells[block.point.y as usize][block.point.x as usize] = Some(block.color);
+        }
+    }
+
+    let active = game.active_piece();
+    if !game.is_over() && active.state == TetronimoState::Falling {
+        let pieces = [game.ghost(active), active.clone()];
+        for block in pieces.iter().flat_map(|p| p.blocks()) {
+            let (x, y) = (block.point.x, block.point.y);
+            if x >= 0 && y >= 0 && (y as usize) < cells.len() && (x as usize) < board.width() {
+                cells[y as usize][x as usize] = Some(block.color);
+            }
+        }
+    }
+    cells
+}
+
+/// Text beside the field: hold, previews and score.
+fn panel(game: &Game) -> Vec<String> {
+    let letter = |shape: Shape| {
+        let mut piece = Tetromino::new_shape(shape);
+        piece.state = TetronimoState::Frozen;
+        format!("{}{}{}", foreground(&piece.color()), shape.letter(), RESET)
+    };
+    let hold = game.held().map(&letter).unwrap_or("-".to_string());
+    let next: Vec<String> = (0..5)
+        .filter_map(|i| game.preview(i))
+        .map(|p| letter(p.shape()))
+        .collect();
+    let mut lines = vec![format!("Hold  {}", hold),
+                         format!("Next  {}", next.join(" ")),
+                         String::new(),
+                         format!("Score {}", game.score()),
+                         format!("Lines {}", game.lines()),
+                         format!("Pieces {}", game.statistics().pieces())];
+    if game.incoming_garbage() > 0 {
+        lines.push(format!("Garbage {}", game.incoming_garbage()));
+    }
+    if let Some(outcome) = game.outcome() {
+        lines.push(String::new());
+        lines.push(match outcome {
+                Outcome::ToppedOut => "Game Over",
+                Outcome::Completed => "Complete",
+                Outcome::Won => "You Win",
+                Outcome::Failed => "Failed",
+            }
+            .to_string());
+    }
+    lines
+}
+
+/// One frame of the game as text with ANSI colors.
+pub fn render(game: &Game, style: Style) -> String {
+    let cells = cells(game);
+    let width = game.board().width();
+    let mut rows: Vec<String> = Vec::new();
+    match style {
+        Style::Blocks => {
+            for row in cells.iter().rev() {
+                let mut line = String::new();
+                for cell in row {
+                    match *cell {
+                        Some(ref color) => line.push_str(&background(color)),
+                        None => line.push_str(RESET),
+                    }
+                    line.push_str("  ");
+                }
+                rows.push(line);
+            }
+        }
+        Style::HalfBlocks => {
+            // From the top down, an odd height leaves an empty bottom half
+            let top = cells.len() as i32 - 1;
+            for upper in (0..(cells.len() + 1) / 2).map(|i| top - 2 * i as i32) {
+                let mut line = String::new();
+                for x in 0..width {
+                    let lower = if upper > 0 { cells[upper as usize - 1][x] } else { None };
+                    let cell = match (cells[upper as usize][x], lower) {
+                        (None, None) => format!("{} ", RESET),
+                        (Some(up), None) => format!("{}{}\u{2580}", RESET, foreground(&up)),
+                        (None, Some(down)) => format!("{}{}\u{2584}", RESET, foreground(&down)),
+                        (Some(up), Some(down)) => {
+                            format!("{}{}\u{2580}", foreground(&up), background(&down))
+                        }
+                    };
+                    line.push_str(&cell);
+                }
+                rows.push(line);
+            }
+        }
+    }
+
+    let inner = match style {
+        Style::Blocks => width * 2,
+        Style::HalfBlocks => width,
+    };
+    let border: String = (0..inner).map(|_| '-').collect();
+    let panel = panel(game);
+    let mut text = format!("+{}+\n", border);
+    for (i, row) in rows.iter().enumerate() {
+        let side = panel.get(i).map(|s| s.as_str()).unwrap_or("");
+        text.push_str(&format!("|{}{}|  {}\n", row, RESET, side));
+    }
+    text.push_str(&format!("+{}+\n", border));
+    text
+}
+
+/// Runs a game in the terminal until it is quit with q or Escape. The
+/// keyboard plays unless a bot plays or a replay is being watched.
+pub fn run(game: &mut Game,
+           mut bot: Option<Bot>,
+           playback: Option<&Replay>,
+           mut broadcaster: Option<&mut Broadcaster>,
+           style: Style)
+           -> Result<()> {
+    let raw = try!(RawMode::enable());
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    try!(write!(out, "\x1b[2J\x1b[?25l"));
+    let mut played = 0;
+    let mut last = Instant::now();
+    loop {
+        let keys = raw.read_keys();
+        if keys.iter().any(is_quit) {
+            break;
+        }
+        let elapsed = last.elapsed();
+        last = Instant::now();
+        let dt = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+
+        match playback {
+            Some(replay) => played = replay.play_frame(game, played),
+            None => {
+                let commands: Vec<Command> = if bot.is_none() {
+                    keys.iter().filter_map(|&key| command(key)).collect()
+                } else {
+                    Vec::new()
+                };
+                if keys.contains(&TermKey::Char('h')) {
+                    game.toggle_hint();
+                }
+                for &c in &commands {
+                    game.on_press(c);
+                }
+                if let Some(ref mut bot) = bot {
+                    bot.on_update(game, dt);
+                }
+                game.on_update(dt);
+                for &c in &commands {
+                    game.on_release(c);
+                }
+            }
+        }
+
+        if let Some(ref mut broadcaster) = broadcaster {
+            broadcaster.publish(game);
+        }
+        try!(write!(out, "\x1b[H{}", render(game, style)));
+        try!(out.flush());
+        thread::sleep(Duration::from_millis((FRAME * 1000f64) as u64));
+    }
+    try!(writeln!(out, ""));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::Game;
+    use input::Command;
+    use mode::Mode;
+    use rules::Ruleset;
+
+    #[test]
+    fn arrows_are_escape_sequences() {
+        assert_eq!(vec![TermKey::Left, TermKey::Char('z'), TermKey::Up, TermKey::Escape],
+                   parse_keys(b"\x1b[Dz\x1b[A\x1b"));
+        assert_eq!(Some(Command::SlideLeft), command(TermKey::Left));
+        assert_eq!(Some(Command::Lock), command(TermKey::Char(' ')));
+        assert_eq!(None, command(TermKey::Char('q')));
+    }
+
+    #[test]
+    fn frames_fit_the_field() {
+        let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), 2);
+        game.on_update(0f64);
+        let blocks = render(&game, Style::Blocks);
+        assert_eq!(20 + 2, blocks.lines().count());
+        assert!(blocks.contains("\x1b[48;2;"));
+        assert!(blocks.contains("Next"));
+        let halves = render(&game, Style::HalfBlocks);
+        assert_eq!(10 + 2, halves.lines().count());
+        assert!(halves.contains('\u{2580}') || halves.contains('\u{2584}'));
+    }
+}
@@ -27,4 +27,13 @@ impl Color {
             alpha: 1f32,
         }
     }
+    /// Garbage rows.
+    pub fn gray() -> Self {
+        Color {
+            red: 0.5f32,
+            green: 0.5f32,
+            blue: 0.5f32,
+            alpha: 1f32,
+        }
+    }
 }
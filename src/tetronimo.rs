@@ -1,15 +1,17 @@
-use rand;
-use rand::{thread_rng, Rng};
-use super::block::{self, Block};
+use rand::{Rng, XorShiftRng};
+use super::block::Block;
+use super::geometry::{self, Bounds};
+use super::seed::{self, Stream};
 use super::transform::{self, Orientation, Point, RotationDirection};
 use super::tetriscolor::Color;
 use na::Origin;
 
 
+use std::ascii::AsciiExt;
 use std::collections::VecDeque;
 use std::cell::RefCell;
 
-fn draw_pieces(rng: &mut rand::ThreadRng) -> Vec<Shape> {
+fn draw_pieces<R: Rng>(rng: &mut R) -> Vec<Shape> {
     let mut new_pieces = vec![
         Shape::O,
         Shape::I,
@@ -27,15 +29,27 @@ fn draw_pieces(rng: &mut rand::ThreadRng) -> Vec<Shape> {
 
 pub struct TetrominoGenerator {
     queue: RefCell<VecDeque<Tetromino>>,
-    rng: RefCell<rand::ThreadRng>,
+    /// Deals more bags when the queue runs low, `None` for a fixed
+    /// sequence that simply runs out.
+    rng: Option<RefCell<XorShiftRng>>,
 }
 
 
 impl TetrominoGenerator {
-    pub fn new() -> Self {
+    /// The same seed always deals the same sequence of pieces.
+    pub fn new(seed: u32) -> Self {
+        let rng = seed::seeded_rng(seed, Stream::Pieces);
         TetrominoGenerator {
             queue: RefCell::new(VecDeque::new()),
-            rng: RefCell::new(rand::thread_rng()),
+            rng: Some(RefCell::new(rng)),
+        }
+    }
+
+    /// Deals exactly these pieces and nothing after them.
+    pub fn fixed(shapes: &[Shape]) -> Self {
+        TetrominoGenerator {
+            queue: RefCell::new(shapes.iter().map(|&s| Tetromino::new_shape(s)).collect()),
+            rng: None,
         }
     }
 
@@ -45,43 +59,49 @@ impl TetrominoGenerator {
     }
 
     fn extend(&self) {
-        let mut rng = self.rng.borrow_mut();
-        let new_shapes = draw_pieces(&mut rng);
+        let mut rng = match self.rng {
+            Some(ref rng) => rng.borrow_mut(),
+            None => return,
+        };
+        let new_shapes = draw_pieces(&mut *rng);
         let mut queue = self.queue.borrow_mut();
         for shape in new_shapes {
             queue.push_back(Tetromino::new_shape(shape))
         }
     }
 
-    pub fn peek(&self, i: usize) -> Tetromino {
+    /// The `i`th piece to come, `None` past the end of a fixed sequence.
+    pub fn peek(&self, i: usize) -> Option<Tetromino> {
         if i >= self.upcoming_queue_length() {
             self.extend();
         }
         let queue = self.queue.borrow();
-        queue.get(i).unwrap().clone()
+        queue.get(i).cloned()
     }
 
-    pub fn pop(&mut self) -> Tetromino {
+    pub fn pop(&mut self) -> Option<Tetromino> {
         if self.upcoming_queue_length() == 0 {
             self.extend();
         }
         let mut queue = self.queue.borrow_mut();
-        queue.pop_front().unwrap()
+        queue.pop_front()
     }
 }
 
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TetronimoState {
     Falling,
     Ghost,
+    /// A suggested placement, drawn a little stronger than the ghost.
+    Hint,
     Locking,
     Frozen,
     Nonexistant,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Shape {
     O,
     T,
@@ -92,65 +112,31 @@ pub enum Shape {
     Z,
 }
 
-#[derive(Debug, Clone)]
-pub enum SlideDirection {
-    Left,
-    Right,
-}
-
-
-
-#[derive(Debug, Clone)]
-pub struct Tetromino {
-    shape: Shape,
-    origin: Point,
-    orientation: Orientation,
-    pub state: TetronimoState,
-}
-
-impl Tetromino {
-    /// Creates a dummy tetronimo
-    pub fn new() -> Self {
-        Tetromino {
-            shape: Shape::O,
-            origin: Point::origin(),
-            orientation: Orientation::North,
-            state: TetronimoState::Nonexistant,
+static SHAPES: [Shape; 7] = [Shape::O, Shape::T, Shape::I, Shape::L, Shape::J, Shape::S, Shape::Z];
+
+impl Shape {
+    /// The letter the shape is named after.
+    pub fn letter(&self) -> char {
+        match *self {
+            Shape::O => 'O',
+            Shape::T => 'T',
+            Shape::I => 'I',
+            Shape::L => 'L',
+            Shape::J => 'J',
+            Shape::S => 'S',
+            Shape::Z => 'Z',
         }
     }
 
-    /// Creates a tetronimo with a desired shape
-    pub fn new_shape(shape: Shape) -> Self {
-        Tetromino {
-            shape: shape,
-            origin: Point::origin(),
-            orientation: Orientation::North,
-            state: TetronimoState::Nonexistant,
-        }
-    }
-
-    pub fn put(&mut self, p: Point) {
-        self.origin = p;
-    }
-
-    /// Color of the tetronimo
+    /// Color of the shape's blocks, fully opaque.
     pub fn color(&self) -> Color {
-
-        let alpha: f32 = match self.state {
-            TetronimoState::Falling => 1f32,
-            TetronimoState::Ghost => 0.2f32,
-            TetronimoState::Locking => 1f32,
-            TetronimoState::Frozen => 1f32,
-            TetronimoState::Nonexistant => 0f32,
-        };
-
-        match self.shape {
+        match *self {
             Shape::O => {
                 Color {
                     red: 0.0,
                     green: 0.0,
                     blue: 1.0,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::T => {
@@ -158,7 +144,7 @@ impl Tetromino {
                     red: 0.7,
                     green: 0.7,
                     blue: 0.0,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::I => {
@@ -166,7 +152,7 @@ impl Tetromino {
                     red: 0.0,
                     green: 0.7,
                     blue: 0.7,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::L => {
@@ -174,7 +160,7 @@ impl Tetromino {
                     red: 0.5,
                     green: 0.7,
                     blue: 0.7,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::J => {
@@ -182,7 +168,7 @@ impl Tetromino {
                     red: 0.5,
                     green: 0.0,
                     blue: 0.7,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::S => {
@@ -190,7 +176,7 @@ impl Tetromino {
                     red: 0.5,
                     green: 0.3,
                     blue: 0.0,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
             Shape::Z => {
@@ -198,12 +184,103 @@ impl Tetromino {
                     red: 0.0,
                     green: 0.3,
                     blue: 0.5,
-                    alpha: alpha,
+                    alpha: 1.0,
                 }
             }
         }
     }
 
+    /// The shape whose blocks are this color, whatever the alpha.
+    pub fn by_color(color: &Color) -> Option<Shape> {
+        Shape::all()
+            .iter()
+            .find(|s| {
+                let c = s.color();
+                c.red == color.red && c.green == color.green && c.blue == color.blue
+            })
+            .cloned()
+    }
+
+    pub fn all() -> &'static [Shape] {
+        &SHAPES
+    }
+
+    pub fn by_letter(letter: char) -> Option<Shape> {
+        Shape::all()
+            .iter()
+            .find(|s| s.letter() == letter.to_ascii_uppercase())
+            .cloned()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlideDirection {
+    Left,
+    Right,
+}
+
+
+
+#[derive(Debug, Clone)]
+pub struct Tetromino {
+    shape: Shape,
+    origin: Point,
+    orientation: Orientation,
+    pub state: TetronimoState,
+}
+
+impl Tetromino {
+    /// Creates a dummy tetronimo
+    pub fn new() -> Self {
+        Tetromino {
+            shape: Shape::O,
+            origin: Point::origin(),
+            orientation: Orientation::North,
+            state: TetronimoState::Nonexistant,
+        }
+    }
+
+    /// Creates a tetronimo with a desired shape
+    pub fn new_shape(shape: Shape) -> Self {
+        Tetromino {
+            shape: shape,
+            origin: Point::origin(),
+            orientation: Orientation::North,
+            state: TetronimoState::Nonexistant,
+        }
+    }
+
+    pub fn put(&mut self, p: Point) {
+        self.origin = p;
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn origin(&self) -> Point {
+        self.origin
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Color of the tetronimo
+    pub fn color(&self) -> Color {
+
+        let alpha: f32 = match self.state {
+            TetronimoState::Falling => 1f32,
+            TetronimoState::Ghost => 0.2f32,
+            TetronimoState::Hint => 0.45f32,
+            TetronimoState::Locking => 1f32,
+            TetronimoState::Frozen => 1f32,
+            TetronimoState::Nonexistant => 0f32,
+        };
+
+        Color { alpha: alpha, ..self.shape.color() }
+    }
+
     pub fn slide(&mut self, direction: SlideDirection) {
         match direction {
             SlideDirection::Left => self.origin.x -= 1,
@@ -227,17 +304,19 @@ impl Tetromino {
         self.origin.y -= 1;
     }
 
+    /// The cells the piece covers, without allocating.
+    pub fn cells(&self) -> [Point; 4] {
+        geometry::cells(self.shape, self.orientation, self.origin)
+    }
+
+    /// The box around `cells`, relative to the origin.
+    pub fn bounds(&self) -> Bounds {
+        geometry::bounds(self.shape, self.orientation)
+    }
+
     pub fn blocks(&self) -> Vec<Block> {
         let color = self.color();
-        tetronimo_points(self.shape)
-            .iter()
-            .map(|p| {
-                let tetronimo_space = transform::transform(&p, &self.orientation);
-                let block_space = block::into_block(&tetronimo_space);
-                let final_point: Point = self.origin + block_space.to_vector();
-                Block::new(color, final_point)
-            })
-            .collect()
+        self.cells().iter().map(|p| Block::new(color, *p)).collect()
     }
 }
 
@@ -374,6 +453,7 @@ fn wall_kicks(shape: &Shape,
     }
 }
 
+#[cfg(test)]
 fn tetronimo_points(shape: Shape) -> Vec<Point> {
     match shape {
         Shape::O => {
@@ -434,3 +514,51 @@ fn tetronimo_points(shape: Shape) -> Vec<Point> {
         }
     }
 }
+
+#[test]
+fn seeded_generators_agree() {
+    let mut a = TetrominoGenerator::new(1234);
+    let mut b = TetrominoGenerator::new(1234);
+    for _ in 0..30 {
+        assert_eq!(a.pop().unwrap().shape(), b.pop().unwrap().shape());
+    }
+}
+
+#[test]
+fn every_bag_has_each_shape() {
+    let mut generator = TetrominoGenerator::new(99);
+    for _ in 0..3 {
+        let mut bag: Vec<Shape> = (0..7).map(|_| generator.pop().unwrap().shape()).collect();
+        bag.sort();
+        assert_eq!(vec![Shape::O, Shape::T, Shape::I, Shape::L, Shape::J, Shape::S, Shape::Z],
+                   bag);
+    }
+}
+
+#[test]
+fn fixed_sequences_run_out() {
+    let mut generator = TetrominoGenerator::fixed(&[Shape::T, Shape::I]);
+    assert_eq!(Some(Shape::I), generator.peek(1).map(|p| p.shape()));
+    assert!(generator.peek(2).is_none());
+    assert_eq!(Some(Shape::T), generator.pop().map(|p| p.shape()));
+    assert_eq!(Some(Shape::I), generator.pop().map(|p| p.shape()));
+    assert!(generator.pop().is_none());
+}
+
+#[test]
+fn geometry_tables_match_transform() {
+    use super::block;
+    for shape in &[Shape::O, Shape::T, Shape::I, Shape::L, Shape::J, Shape::S, Shape::Z] {
+        for orientation in &[Orientation::North,
+                             Orientation::East,
+                             Orientation::South,
+                             Orientation::West] {
+            let computed: Vec<(i32, i32)> = tetronimo_points(*shape)
+                .iter()
+                .map(|p| block::into_block(&transform::transform(p, orientation)))
+                .map(|p| (p.x, p.y))
+                .collect();
+            assert_eq!(&computed[..], &geometry::offsets(*shape, *orientation)[..]);
+        }
+    }
+}
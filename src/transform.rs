@@ -5,7 +5,7 @@ pub type Matrix = na::Matrix2<i32>;
 
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Orientation {
     North,
     East,
@@ -14,7 +14,7 @@ pub enum Orientation {
 }
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RotationDirection {
     Clockwise,
     CounterClockwise,
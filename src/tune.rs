@@ -0,0 +1,283 @@
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+use toml;
+
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use super::bot::{self, Weights};
+use super::cli;
+use super::error::Error;
+use super::game::Game;
+use super::mode::Mode;
+use super::rules::Ruleset;
+use super::seed::{self, Stream};
+use super::Result;
+
+/// How the weights are evolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub population: usize,
+    pub generations: usize,
+    /// Games each candidate plays per generation, all on the same seeds.
+    pub games: usize,
+    /// Pieces after which a game is cut short.
+    pub pieces: usize,
+    pub threads: usize,
+    pub seed: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            population: 40,
+            generations: 20,
+            games: 4,
+            pieces: 500,
+            threads: 4,
+            seed: 1,
+        }
+    }
+}
+
+/// A set of weights and the average number of lines it cleared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub weights: Weights,
+    pub fitness: f64,
+}
+
+/// Lines cleared by the bot in a marathon, cut short after `pieces`.
+pub fn play(weights: &Weights, seed: u32, pieces: usize) -> usize {
+    let mut game = Game::new(Mode::Marathon, Ruleset::guideline(), seed);
+    while game.statistics().pieces() < pieces && bot::play_piece(&mut game, weights, 0) {}
+    game.lines()
+}
+
+fn fitness(weights: &Weights, seeds: &[u32], pieces: usize) -> f64 {
+    let lines: usize = seeds.iter().map(|&seed| play(weights, seed, pieces)).sum();
+    lines as f64 / seeds.len().max(1) as f64
+}
+
+/// Fitness of every candidate, playing on `threads` threads at once.
+pub fn evaluate(population: &[Weights], seeds: &[u32], pieces: usize, threads: usize) -> Vec<f64> {
+    let threads = threads.max(1);
+    let population = Arc::new(population.to_vec());
+    let seeds = Arc::new(seeds.to_vec());
+    // Candidates are dealt out in turn, since bad ones top out early
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let population = population.clone();
+            let seeds = seeds.clone();
+            thread::spawn(move || {
+                (0..population.len())
+                    .filter(|i| i % threads == t)
+                    .map(|i| (i, fitness(&population[i], &seeds, pieces)))
+                    .collect::<Vec<(usize, f64)>>()
+            })
+        })
+        .collect();
+
+    let mut result = vec![0f64; population.len()];
+    for handle in handles {
+        for (i, f) in handle.join().expect("Tuning thread panicked") {
+            result[i] = f;
+        }
+    }
+    result
+}
+
+/// Scales the weights to unit length, which does not change the moves
+/// they pick but keeps candidates comparable.
+fn normalize(values: &[f64]) -> Weights {
+    let length = values.iter().map(|v| v * v).sum::<f64>().sqrt();
+    if length == 0f64 {
+        return Weights::from_slice(values);
+    }
+    let scaled: Vec<f64> = values.iter().map(|v| v / length).collect();
+    Weights::from_slice(&scaled)
+}
+
+fn random_weights<R: Rng>(rng: &mut R) -> Weights {
+    let values: Vec<f64> = (0..bot::WEIGHT_NAMES.len())
+        .map(|_| rng.gen_range(-1f64, 1f64))
+        .collect();
+    normalize(&values)
+}
+
+/// Best of a few random candidates, `ranked` is sorted best first.
+fn tournament<'a, R: Rng>(ranked: &'a [Candidate], rng: &mut R) -> &'a Candidate {
+    let size = (ranked.len() / 10).max(2);
+    let best = (0..size).map(|_| rng.gen_range(0, ranked.len())).min().unwrap();
+    &ranked[best]
+}
+
+/// Average of the parents, leaning towards the fitter one.
+fn crossover(a: &Candidate, b: &Candidate) -> Vec<f64> {
+    let total = a.fitness + b.fitness;
+    let share = if total > 0f64 { a.fitness / total } else { 0.5 };
+    a.weights
+        .to_vec()
+        .iter()
+        .zip(b.weights.to_vec())
+        .map(|(x, y)| x * share + y * (1f64 - share))
+        .collect()
+}
+
+/// The next population: the best tenth as they are, the rest bred from
+/// tournament winners and sometimes nudged in one direction.
+fn breed<R: Rng>(ranked: &[Candidate], rng: &mut R) -> Vec<Weights> {
+    let elite = (ranked.len() / 10).max(1);
+    let mut next: Vec<Weights> = ranked[..elite].iter().map(|c| c.weights).collect();
+    let nudge = Normal::new(0f64, 0.2);
+    while next.len() < ranked.len() {
+        let mut child = crossover(tournament(ranked, rng), tournament(ranked, rng));
+        if rng.gen_weighted_bool(2) {
+            let i = rng.gen_range(0, child.len());
+            child[i] += nudge.ind_sample(rng);
+        }
+        next.push(normalize(&child));
+    }
+    next
+}
+
+/// Runs the genetic algorithm, calling `report` with every generation
+/// ranked best first. Returns the last generation, best first.
+pub fn evolve<F>(settings: &Settings, mut report: F) -> Vec<Candidate>
+    where F: FnMut(usize, &[Candidate])
+{
+    let mut rng = seed::seeded_rng(settings.seed, Stream::Tuning);
+    let mut population: Vec<Weights> = (0..settings.population.max(2))
+        .map(|_| random_weights(&mut rng))
+        .collect();
+    // The hand picked weights compete too
+    population[0] = normalize(&Weights::default().to_vec());
+
+    let mut ranked = Vec::new();
+    for generation in 0..settings.generations {
+        // Fresh games every generation, so no candidate gets lucky twice
+        let seeds: Vec<u32> = (0..settings.games).map(|_| rng.gen()).collect();
+        let fitness = evaluate(&population, &seeds, settings.pieces, settings.threads);
+        ranked = population.iter()
+            .zip(fitness)
+            .map(|(&weights, fitness)| {
+                Candidate {
+                    weights: weights,
+                    fitness: fitness,
+                }
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap_or(Ordering::Equal));
+        report(generation, &ranked);
+        population = breed(&ranked, &mut rng);
+    }
+    ranked
+}
+
+/// The best `keep` candidates as a `[[weights]]` list, readable by
+/// `Weights::load`.
+pub fn to_text(ranked: &[Candidate], keep: usize) -> String {
+    let mut text = String::from("# Written by testris tune, best first\n");
+    for candidate in ranked.iter().take(keep) {
+        let mut table = match candidate.weights.to_toml() {
+            toml::Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        table.insert("fitness".to_string(), toml::Value::Float(candidate.fitness));
+        text.push_str(&format!("\n[[weights]]\n{}", toml::Value::Table(table)));
+    }
+    text
+}
+
+pub fn save(path: &Path, ranked: &[Candidate], keep: usize) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)
+        .and_then(|_| File::create(path))
+        .and_then(|mut file| write!(file, "{}", to_text(ranked, keep)))
+        .map_err(|e| Error::from(e).in_file(path))
+}
+
+/// `testris tune`, evolves weights and writes the best ones to a file.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    if args.iter().any(|a| a == "--help") {
+        println!("usage: testris tune [--population N] [--generations N] [--games N] \
+                  [--pieces N] [--threads N] [--seed N] [--keep N] [--output FILE]");
+        return Ok(());
+    }
+    let defaults = Settings::default();
+    let settings = Settings {
+        population: try!(cli::number_flag(args, "--population", defaults.population)),
+        generations: try!(cli::number_flag(args, "--generations", defaults.generations)),
+        games: try!(cli::number_flag(args, "--games", defaults.games)),
+        pieces: try!(cli::number_flag(args, "--pieces", defaults.pieces)),
+        threads: try!(cli::number_flag(args, "--threads", defaults.threads)),
+        seed: try!(cli::number_flag(args, "--seed", defaults.seed)),
+    };
+    let keep = try!(cli::number_flag(args, "--keep", 5usize));
+    let output = PathBuf::from(cli::flag_value(args, "--output").unwrap_or("weights.toml"));
+
+    let ranked = evolve(&settings, |generation, ranked| {
+        let mean = ranked.iter().map(|c| c.fitness).sum::<f64>() / ranked.len() as f64;
+        println!("generation {:>3}: best {:>8.1} lines  mean {:>8.1}  {:?}",
+                 generation + 1,
+                 ranked[0].fitness,
+                 mean,
+                 ranked[0].weights);
+        // Keep what we have so far in case the run is cut short
+        if let Err(e) = save(&output, ranked, keep) {
+            error!("Could not save weights: {}", e);
+        }
+    });
+    try!(save(&output, &ranked, keep));
+    println!("Wrote {} weight sets to {}", keep.min(ranked.len()), output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bot::Weights;
+
+    #[test]
+    fn threads_do_not_change_results() {
+        let population = vec![Weights::default(), Weights::from_slice(&[-1.0, 0.0, 0.0, 0.0, 0.0])];
+        let seeds = [3, 4];
+        let alone = evaluate(&population, &seeds, 30, 1);
+        assert_eq!(alone, evaluate(&population, &seeds, 30, 3));
+        assert!(alone[0] > 0f64);
+    }
+
+    #[test]
+    fn saved_weights_load_best_first() {
+        let best = Candidate {
+            weights: Weights::from_slice(&[-0.5, -0.7, -0.2, -0.1, 0.4]),
+            fitness: 80.5,
+        };
+        let worse = Candidate {
+            weights: Weights::default(),
+            fitness: 12.0,
+        };
+        let text = to_text(&[best, worse], 5);
+        assert_eq!(best.weights, Weights::parse(&text).unwrap());
+        assert!(Weights::parse("[[weights]]\nholes = 1.0\n").is_err());
+    }
+
+    #[test]
+    fn evolving_is_seeded() {
+        let settings = Settings {
+            population: 4,
+            generations: 2,
+            games: 1,
+            pieces: 20,
+            threads: 2,
+            seed: 9,
+        };
+        let first = evolve(&settings, |_, _| {});
+        assert_eq!(first, evolve(&settings, |_, _| {}));
+        assert_eq!(4, first.len());
+    }
+}
@@ -0,0 +1,131 @@
+use piston_window::*;
+
+use super::board::Dimensions;
+use super::game::Game;
+use super::input::KeyMap;
+use super::mode::{Mode, Outcome};
+use super::rules::Ruleset;
+use super::Result;
+
+/// Two players side by side on one keyboard, sending each other
+/// garbage until one of them tops out.
+pub struct Versus {
+    players: [Game; 2],
+}
+
+impl Versus {
+    /// Both players get the same pieces, the left one plays with WASD
+    /// and the right one with the arrow keys.
+    pub fn new(ruleset: Ruleset, seed: u32, dimensions: Dimensions) -> Result<Versus> {
+        let mut left = try!(Game::with_dimensions(Mode::Marathon, ruleset, seed, dimensions));
+        let mut right = try!(Game::with_dimensions(Mode::Marathon, ruleset, seed, dimensions));
+        left.set_key_map(KeyMap::left_player());
+        right.set_key_map(KeyMap::right_player());
+        Ok(Versus { players: [left, right] })
+    }
+
+    /// Room for both players next to each other.
+    pub fn window_size(&self) -> [u32; 2] {
+        let size = self.players[0].window_size();
+        [size[0] * 2, size[1]]
+    }
+
+    pub fn players(&self) -> &[Game; 2] {
+        &self.players
+    }
+
+    pub fn players_mut(&mut self) -> &mut [Game; 2] {
+        &mut self.players
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.players.iter().any(|p| p.is_over())
+    }
+
+    /// Index of the player who won, `None` while playing or after a draw.
+    pub fn winner(&self) -> Option<usize> {
+        self.players.iter().position(|p| p.outcome() == Some(Outcome::Won))
+    }
+
+    /// Both players see every key, each one reacts to its own.
+    pub fn on_input(&mut self, input: &Input) {
+        for player in &mut self.players {
+            player.on_input(input);
+        }
+    }
+
+    /// Advances both games, then delivers the attacks made this frame
+    /// and ends the match once someone has topped out.
+    pub fn on_update(&mut self, dt: f64) {
+        for player in &mut self.players {
+            player.on_update(dt);
+        }
+        let attacks = [self.players[0].take_attack(), self.players[1].take_attack()];
+        self.players[1].receive_garbage(attacks[0]);
+        self.players[0].receive_garbage(attacks[1]);
+
+        let topped_out: Vec<bool> = self.players
+            .iter()
+            .map(|p| p.outcome() == Some(Outcome::ToppedOut))
+            .collect();
+        // Topping out on the same frame is a draw
+        match (topped_out[0], topped_out[1]) {
+            (true, false) => self.players[1].win(),
+            (false, true) => self.players[0].win(),
+            _ => {}
+        }
+    }
+
+    pub fn on_render<G>(&self, g: &mut G, view: math::Matrix2d)
+        where G: Graphics
+    {
+        let width = self.players[0].window_size()[0] as f64;
+        self.players[0].on_render(g, view);
+        self.players[1].on_render(g, view.trans(width, 0f64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bot::{self, Weights};
+    use board::Dimensions;
+    use mode::Outcome;
+    use rules::Ruleset;
+
+    fn versus() -> Versus {
+        Versus::new(Ruleset::guideline(), 4, Dimensions::standard()).unwrap()
+    }
+
+    #[test]
+    fn garbage_rises_after_a_lock() {
+        let mut versus = versus();
+        let game = &mut versus.players_mut()[0];
+        game.receive_garbage(2);
+        game.receive_garbage(3);
+        assert_eq!(5, game.incoming_garbage());
+
+        // Nothing is cleared, so all of it comes up under the piece
+        assert!(bot::play_piece(game, &Weights::default(), 0));
+        assert_eq!(0, game.incoming_garbage());
+        let bits = game.board().bits();
+        for y in 0..5 {
+            assert_eq!(9, bits.row(y).count_ones());
+        }
+        assert_eq!(5 * 9 + 4, bits.count());
+        assert!(game.replay().to_string().contains("\ng 3\n"));
+    }
+
+    #[test]
+    fn burying_the_opponent_wins() {
+        let mut versus = versus();
+        versus.on_update(0f64);
+        assert_eq!(None, versus.winner());
+        versus.players_mut()[1].receive_garbage(25);
+        bot::play_piece(&mut versus.players_mut()[1], &Weights::default(), 0);
+        versus.on_update(0f64);
+        assert!(versus.is_over());
+        assert_eq!(Some(0), versus.winner());
+        assert_eq!(Some(Outcome::ToppedOut), versus.players()[1].outcome());
+    }
+}